@@ -0,0 +1,132 @@
+use jieba_rs::Jieba;
+use std::sync::OnceLock;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Counts the words of `text`, cutting Chinese text, which is written
+/// without spaces, with a dictionary.
+///
+/// Text is first split at UAX #29 word boundaries, which gives whole words
+/// for space-separated scripts, Korean included, and runs of Katakana. Runs
+/// of Han and Hiragana, which UAX #29 splits into single characters, are
+/// cut into words with the Chinese dictionary bundled with `jieba`.
+///
+/// No Japanese dictionary is bundled. Runs holding Hiragana are only cut
+/// where the script changes between Kanji and Hiragana, which splits verb
+/// stems from their endings, and Japanese written in Kanji alone goes
+/// through the Chinese dictionary. Segments made only of punctuation or
+/// spaces are not words.
+pub(crate) fn count_words(text: &str) -> usize {
+    let mut words = 0;
+    let mut run_start = None;
+    for (start, segment) in text.split_word_bound_indices() {
+        if segment.starts_with(is_ideographic) {
+            run_start.get_or_insert(start);
+            continue;
+        }
+        if let Some(run_start) = run_start.take() {
+            words += count_dictionary_words(&text[run_start..start]);
+        }
+        words += is_word(segment) as usize;
+    }
+    if let Some(run_start) = run_start {
+        words += count_dictionary_words(&text[run_start..]);
+    }
+    words
+}
+
+/// Counts the words of a run of Han and Hiragana characters.
+fn count_dictionary_words(run: &str) -> usize {
+    if run.contains(is_hiragana) {
+        return count_japanese_words(run);
+    }
+    jieba()
+        .cut(run, true)
+        .into_iter()
+        .filter(|word| is_word(word))
+        .count()
+}
+
+/// Counts the words of a Japanese run of Kanji and Hiragana characters, one
+/// for each stretch written in a single script. This keeps a word spelled
+/// in kana like `こんにちは` whole, but over-splits inflected words such as
+/// `住んでいます`.
+fn count_japanese_words(run: &str) -> usize {
+    let mut words = 0;
+    let mut last = None;
+    for c in run.chars() {
+        let hiragana = is_hiragana(c);
+        if last != Some(hiragana) {
+            words += 1;
+            last = Some(hiragana);
+        }
+    }
+    words
+}
+
+/// Returns the segmenter, loading its dictionary on first use.
+fn jieba() -> &'static Jieba {
+    static JIEBA: OnceLock<Jieba> = OnceLock::new();
+    JIEBA.get_or_init(Jieba::new)
+}
+
+/// Returns whether `c` can only start a new sentence after a terminator
+/// like `。`: an ideograph or a kana or Hangul letter.
+pub(crate) fn starts_sentence(c: char) -> bool {
+    is_ideographic(c)
+        || matches!(c,
+            '\u{30A1}'..='\u{30FA}' // Katakana letters
+            | '\u{AC00}'..='\u{D7A3}' // Hangul syllables
+        )
+}
+
+fn is_word(segment: &str) -> bool {
+    segment.chars().any(char::is_alphanumeric)
+}
+
+fn is_hiragana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{309F}')
+}
+
+/// Returns whether `c` belongs to a script written without spaces between
+/// words, which UAX #29 splits into single characters.
+fn is_ideographic(c: char) -> bool {
+    matches!(c,
+        '\u{3005}' | '\u{3007}' // 々, 〇
+        | '\u{3041}'..='\u{309F}' // Hiragana
+        | '\u{3400}'..='\u{4DBF}' // CJK Unified Ideographs Extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK Unified Ideographs
+        | '\u{F900}'..='\u{FAFF}' // CJK Compatibility Ideographs
+        | '\u{20000}'..='\u{323AF}' // Supplementary Ideographic Planes
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chinese_words() {
+        assert_eq!(count_words("我们中出了一个叛徒"), 5);
+        assert_eq!(count_words("我来到北京清华大学。"), 4);
+    }
+
+    #[test]
+    fn test_japanese_words() {
+        assert_eq!(count_words("こんにちは"), 1);
+        assert_eq!(count_words("私は学生です。"), 4);
+        assert_eq!(count_words("ありがとう"), 1);
+    }
+
+    #[test]
+    fn test_mixed_scripts() {
+        assert_eq!(count_words("Rust 是一门编程语言！"), 4);
+        assert_eq!(count_words("안녕하세요 세계"), 2);
+        assert_eq!(count_words("テキスト"), 1);
+    }
+
+    #[test]
+    fn test_punctuation_only() {
+        assert_eq!(count_words(""), 0);
+        assert_eq!(count_words("。！？ ... "), 0);
+    }
+}
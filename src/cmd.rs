@@ -1,9 +1,18 @@
-use clap::{Parser, ValueEnum};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+use globset::{Glob, GlobSet};
+use rust_i18n::t;
 use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 
-use crate::counts::CountOptions;
-use crate::input_processor::process_inputs;
+use crate::counter::Counter;
+use crate::counts::{CountOptions, WordMode};
+use crate::input_processor::{process_inputs, ProcessOptions, TotalMode};
 use crate::locales;
+use crate::output::OutputFormat;
+use crate::tokenizer::{LoadError, Tokenizer, TokenizerModel};
+use crate::walk::{self, WalkOptions};
 
 #[derive(Parser)]
 #[command(author, version, about = "A simple count program by Rust and Cursor")]
@@ -16,49 +25,139 @@ pub struct Cli {
     #[arg(short = 'w', long)]
     words: bool,
 
+    /// How to split words: ascii splits on ASCII whitespace, unicode uses
+    /// Unicode word boundaries, cjk also cuts Chinese text with a dictionary
+    /// (Japanese text is only cut where it switches between kanji and hiragana)
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = WordMode::Ascii)]
+    word_mode: WordMode,
+
+    /// Show sentence count
+    #[arg(short = 's', long)]
+    sentences: bool,
+
+    /// Show paragraph count
+    #[arg(short = 'p', long)]
+    paragraphs: bool,
+
     /// Show character count
-    #[arg(short = 'c', long)]
+    #[arg(short = 'c', long, short_alias = 'm')]
     chars: bool,
 
+    /// Show grapheme cluster count
+    #[arg(short = 'g', long)]
+    graphemes: bool,
+
+    /// Show byte count
+    #[arg(short = 'b', long)]
+    bytes: bool,
+
+    /// Show the display width of the longest line
+    #[arg(short = 'L', long)]
+    max_line_length: bool,
+
     /// Show token count
     #[arg(short = 't', long)]
     tokens: bool,
 
-    /// Choose tokenizer model
-    #[arg(long, value_enum, default_value = "gpt3")]
-    #[clap(long_help = "Choose tokenizer model:
+    /// Choose tokenizer models, one token column each [default: gpt3]
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "MODEL[,MODEL...]"
+    )]
+    #[clap(
+        long_help = "Choose tokenizer models, comma separated or repeated, one token column each [default: gpt3]:
 gpt3    -> r50k_base
 edit    -> p50k_edit
 code    -> p50k_base
 chatgpt -> cl100k_base
-gpt4o   -> o200k_base")]
-    model: Option<TokenizerModel>,
+gpt4o   -> o200k_base"
+    )]
+    model: Vec<TokenizerModel>,
+
+    /// Count tokens with a HuggingFace tokenizer.json file
+    #[arg(long, value_name = "PATH")]
+    tokenizer_file: Option<PathBuf>,
+
+    /// Count tokens with a SentencePiece .model file
+    #[arg(long, value_name = "PATH")]
+    sentencepiece_model: Option<PathBuf>,
+
+    /// Count tokens with a .tiktoken rank file
+    #[arg(long, value_name = "PATH", requires = "pattern")]
+    encoding_file: Option<PathBuf>,
+
+    /// Regex splitting text before merging, for --encoding-file
+    #[arg(long, value_name = "REGEX", requires = "encoding_file")]
+    pattern: Option<String>,
+
+    /// JSON file mapping special tokens, each counted as one token, to ranks, for --encoding-file
+    #[arg(long, value_name = "PATH", requires = "encoding_file")]
+    special: Option<PathBuf>,
+
+    /// Number of files to count in parallel [default: number of CPUs]
+    #[arg(short = 'j', long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Output format
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// When to print the total row
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = TotalMode::Auto)]
+    total: TotalMode,
+
+    /// Print a header row naming each column above text output
+    #[arg(long)]
+    header: bool,
+
+    /// Count the files inside directories, walking them recursively
+    #[arg(short = 'r', long)]
+    recursive: bool,
+
+    /// Walk at most N levels below each directory
+    #[arg(long, value_name = "N", requires = "recursive")]
+    max_depth: Option<usize>,
+
+    /// Include hidden files and directories when walking directories
+    #[arg(long, requires = "recursive")]
+    hidden: bool,
+
+    /// Walk files excluded by .gitignore, .ignore, .tcignore or global git excludes
+    #[arg(long, requires = "recursive")]
+    no_ignore: bool,
+
+    /// Count only files matching GLOB, by name, or by path if it has a '/'
+    #[arg(long, value_name = "GLOB", value_parser = walk::parse_glob)]
+    include: Vec<Glob>,
+
+    /// Skip files and directories matching GLOB, by name, or by path if it has a '/'
+    #[arg(long, value_name = "GLOB", value_parser = walk::parse_glob)]
+    exclude: Vec<Glob>,
 
     /// Input files
     #[arg(name = "FILE")]
     files: Vec<String>,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
-pub enum TokenizerModel {
-    GPT3,
-    Edit,
-    Code,
-    #[clap(name = "chatgpt")]
-    ChatGPT,
-    GPT4O,
-}
-
 impl Cli {
-    fn parse_args() -> (Self, CountOptions) {
+    fn parse_args() -> io::Result<(Self, CountOptions, Vec<String>)> {
         let cli = Self::parse();
+        let (tokenizer_names, tokenizers) = cli.tokenizers()?.into_iter().unzip();
 
         let options = CountOptions {
             show_lines: cli.lines,
             show_words: cli.words,
+            show_sentences: cli.sentences,
+            show_paragraphs: cli.paragraphs,
             show_chars: cli.chars,
+            show_graphemes: cli.graphemes,
+            show_bytes: cli.bytes,
+            show_max_line_length: cli.max_line_length,
             show_tokens: cli.tokens,
-            tokenizer_model: cli.model.unwrap_or(TokenizerModel::GPT3),
+            word_mode: cli.word_mode,
+            tokenizers,
         };
 
         let options = if options.count_enabled_options() == 0 {
@@ -67,18 +166,99 @@ impl Cli {
                 show_words: true,
                 show_chars: true,
                 show_tokens: true,
-                tokenizer_model: options.tokenizer_model,
+                ..options
             }
         } else {
             options
         };
 
-        (cli, options)
+        Ok((cli, options, tokenizer_names))
+    }
+
+    /// Returns the tokenizers to count with, each with the name it is shown
+    /// under: the chosen models followed by the ones loaded from files, or
+    /// GPT-3 if none was chosen.
+    fn tokenizers(&self) -> io::Result<Vec<(String, Tokenizer)>> {
+        let mut tokenizers: Vec<(String, Tokenizer)> = self
+            .model
+            .iter()
+            .map(|&model| (model_name(model), model.into()))
+            .collect();
+        if let Some(path) = &self.tokenizer_file {
+            let tokenizer = loaded(path, Tokenizer::from_hugging_face_file(path))?;
+            tokenizers.push((path.display().to_string(), tokenizer));
+        }
+        if let Some(path) = &self.sentencepiece_model {
+            let tokenizer = loaded(path, Tokenizer::from_sentencepiece_file(path))?;
+            tokenizers.push((path.display().to_string(), tokenizer));
+        }
+        if let Some(path) = &self.encoding_file {
+            let pattern = self.pattern.as_deref().unwrap_or_default();
+            let tokenizer = Tokenizer::from_encoding_files(path, pattern, self.special.as_deref());
+            tokenizers.push((path.display().to_string(), loaded(path, tokenizer)?));
+        }
+        if tokenizers.is_empty() {
+            let model = TokenizerModel::GPT3;
+            tokenizers.push((model_name(model), model.into()));
+        }
+        Ok(tokenizers)
     }
 }
 
+/// Returns a set matching any of `globs`, exiting like an invalid argument
+/// if they cannot be combined.
+fn glob_set(globs: &[Glob]) -> GlobSet {
+    walk::glob_set(globs)
+        .unwrap_or_else(|err| Cli::command().error(ErrorKind::ValueValidation, err).exit())
+}
+
+/// Returns the name `model` is chosen by on the command line.
+fn model_name(model: TokenizerModel) -> String {
+    model
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string())
+}
+
+fn loaded(path: &Path, tokenizer: Result<Tokenizer, LoadError>) -> io::Result<Tokenizer> {
+    tokenizer.map_err(|err| {
+        eprintln!(
+            "{}",
+            t!(
+                "error_loading_tokenizer",
+                filename = path.display(),
+                error = err
+            )
+        );
+        io::Error::other("")
+    })
+}
+
+/// Runs the `tc` command line interface with the process arguments.
 pub fn run() -> io::Result<()> {
     locales::setup_localization();
-    let (cli, options) = Cli::parse_args();
-    process_inputs(&cli.files, &mut io::stdout(), &options)
+    let (cli, options, tokenizer_names) = Cli::parse_args()?;
+    let mut process_options = ProcessOptions {
+        format: cli.format,
+        total: cli.total,
+        header: cli.header,
+        tokenizer_names,
+        walk: WalkOptions {
+            recursive: cli.recursive,
+            max_depth: cli.max_depth,
+            hidden: cli.hidden,
+            no_ignore: cli.no_ignore,
+            include: (!cli.include.is_empty()).then(|| glob_set(&cli.include)),
+            exclude: glob_set(&cli.exclude),
+        },
+        ..ProcessOptions::default()
+    };
+    if let Some(jobs) = cli.jobs {
+        process_options.jobs = jobs.get();
+    }
+    process_inputs(
+        &cli.files,
+        &mut io::stdout(),
+        &Counter::new(options),
+        &process_options,
+    )
 }
@@ -0,0 +1,1121 @@
+use crate::cjk;
+use crate::counts::{CountOptions, InputCounts, WordMode};
+use crate::tokenizer::{TokenizeError, Tokenizer, TokenizerModel};
+use regex::Regex;
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::OnceLock;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+/// Size of the buffer inputs are streamed through.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Most bytes of a single sentence held before it is counted in pieces.
+const MAX_SENTENCE_SIZE: usize = 64 * 1024;
+
+/// Tab stops are this many columns apart when measuring line widths.
+const TAB_WIDTH: usize = 8;
+
+/// Counts lines, words, characters and tokens according to a set of
+/// [`CountOptions`].
+///
+/// ```
+/// use tc::{CountOptions, Counter, TokenizerModel};
+///
+/// let mut options = CountOptions::default();
+/// options.show_tokens = true;
+/// options.tokenizers = vec![TokenizerModel::GPT4O.into()];
+/// let counter = Counter::new(options);
+/// assert_eq!(counter.count_str("Hello, world!").unwrap().tokens, [4]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Counter {
+    options: CountOptions,
+}
+
+impl Counter {
+    /// Creates a counter that computes the counts enabled in `options`.
+    pub fn new(options: CountOptions) -> Self {
+        Self { options }
+    }
+
+    /// Returns the options this counter was created with.
+    pub fn options(&self) -> &CountOptions {
+        &self.options
+    }
+
+    /// Counts everything that can be read from `reader`.
+    ///
+    /// The input is streamed through a fixed-size buffer, and text is only
+    /// held until the counts of the part read so far are known, so memory
+    /// use does not grow with the input size. Tokens are counted in chunks
+    /// that give exactly the same count as tokenizing the whole input at
+    /// once, except with tokenizers that do not support chunking, which hold
+    /// the whole input.
+    /// Invalid UTF-8 sequences are counted as U+FFFD replacement characters.
+    pub fn count_reader<R: Read>(&self, mut reader: R) -> Result<InputCounts, CountError> {
+        let mut state = CountState::new(&self.options);
+        let mut buffer = vec![0; BUFFER_SIZE];
+        // Bytes of an incomplete UTF-8 sequence left over from the previous read.
+        let mut carry = 0;
+
+        loop {
+            let read = match reader.read(&mut buffer[carry..]) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(CountError::Read(err)),
+            };
+            state.counts.bytes += read;
+            let filled = carry + read;
+            carry = decode_utf8(&buffer[..filled], false, |text| state.feed(text));
+            buffer.copy_within(filled - carry..filled, 0);
+        }
+        decode_utf8(&buffer[..carry], true, |text| state.feed(text));
+
+        state.finish().map_err(CountError::Tokenize)
+    }
+
+    /// Counts everything in `text`.
+    pub fn count_str(&self, text: &str) -> Result<InputCounts, TokenizeError> {
+        let mut state = CountState::new(&self.options);
+        state.counts.bytes = text.len();
+        state.feed(text);
+        state.finish()
+    }
+
+    /// Counts a byte buffer, treating invalid UTF-8 like
+    /// [`count_reader`](Self::count_reader) does.
+    pub fn count_bytes(&self, buffer: &[u8]) -> Result<InputCounts, TokenizeError> {
+        let mut state = CountState::new(&self.options);
+        state.counts.bytes = buffer.len();
+        decode_utf8(buffer, true, |text| state.feed(text));
+        state.finish()
+    }
+}
+
+/// The error returned when an input cannot be counted.
+#[derive(Debug)]
+pub enum CountError {
+    /// The input could not be read.
+    Read(io::Error),
+    /// A tokenizer could not tokenize the input.
+    Tokenize(TokenizeError),
+}
+
+impl fmt::Display for CountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CountError::Read(err) => write!(f, "{}", err),
+            CountError::Tokenize(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for CountError {}
+
+/// Decodes `bytes` the way `String::from_utf8_lossy` does, passing the text
+/// to `feed` piece by piece.
+///
+/// Unless `eof` is set, an incomplete sequence at the very end of `bytes` is
+/// not decoded, since the next read may complete it. Returns the number of
+/// such trailing bytes.
+fn decode_utf8(bytes: &[u8], eof: bool, mut feed: impl FnMut(&str)) -> usize {
+    let mut chunks = bytes.utf8_chunks().peekable();
+    while let Some(chunk) = chunks.next() {
+        feed(chunk.valid());
+        let invalid = chunk.invalid();
+        if invalid.is_empty() {
+            continue;
+        }
+        let incomplete = std::str::from_utf8(invalid).is_err_and(|err| err.error_len().is_none());
+        if !eof && incomplete && chunks.peek().is_none() {
+            return invalid.len();
+        }
+        feed("\u{FFFD}");
+    }
+    0
+}
+
+/// Running counts for an input that is fed in pieces.
+struct CountState<'a> {
+    options: &'a CountOptions,
+    counts: InputCounts,
+    in_word: bool,
+    /// The running word count, unless words are split on ASCII whitespace.
+    segmented_words: Option<SegmentCount>,
+    graphemes: Option<SegmentCount>,
+    sentences: Option<SegmentCount>,
+    /// Whether the current line holds only whitespace so far.
+    blank_line: bool,
+    in_paragraph: bool,
+    /// Display width of the current line so far.
+    line_width: usize,
+    last_byte: Option<u8>,
+    /// One running count per tokenizer, if tokens are counted.
+    tokens: Vec<ChunkedTokenCount<'a>>,
+}
+
+impl<'a> CountState<'a> {
+    fn new(options: &'a CountOptions) -> Self {
+        let tokenizers = if options.show_tokens {
+            options.tokenizers.as_slice()
+        } else {
+            &[]
+        };
+        let tokens = tokenizers
+            .iter()
+            .map(|tokenizer| {
+                let chunk_size = if tokenizer.supports_chunking() {
+                    BUFFER_SIZE
+                } else {
+                    usize::MAX
+                };
+                ChunkedTokenCount::new(tokenizer, chunk_size)
+            })
+            .collect();
+        let segmented_words = (options.show_words && options.word_mode != WordMode::Ascii)
+            .then(|| SegmentCount::new(Segment::Word(options.word_mode)));
+        let graphemes = options
+            .show_graphemes
+            .then(|| SegmentCount::new(Segment::Grapheme));
+        let sentences = options
+            .show_sentences
+            .then(|| SegmentCount::new(Segment::Sentence));
+        Self {
+            options,
+            counts: InputCounts::default(),
+            in_word: false,
+            segmented_words,
+            graphemes,
+            sentences,
+            blank_line: true,
+            in_paragraph: false,
+            line_width: 0,
+            last_byte: None,
+            tokens,
+        }
+    }
+
+    fn feed(&mut self, text: &str) {
+        let Some(&last_byte) = text.as_bytes().last() else {
+            return;
+        };
+        let options = self.options;
+
+        if options.show_lines {
+            self.counts.lines += text.bytes().filter(|&b| b == b'\n').count();
+        }
+        if let Some(words) = &mut self.segmented_words {
+            words.feed(text);
+        } else if options.show_words {
+            for b in text.bytes() {
+                if b.is_ascii_whitespace() {
+                    self.in_word = false;
+                } else if !self.in_word {
+                    self.in_word = true;
+                    self.counts.words += 1;
+                }
+            }
+        }
+        if let Some(sentences) = &mut self.sentences {
+            sentences.feed(text);
+        }
+        if options.show_paragraphs {
+            for c in text.chars() {
+                if c == '\n' {
+                    self.in_paragraph &= !self.blank_line;
+                    self.blank_line = true;
+                } else if !c.is_whitespace() {
+                    if !self.in_paragraph {
+                        self.in_paragraph = true;
+                        self.counts.paragraphs += 1;
+                    }
+                    self.blank_line = false;
+                }
+            }
+        }
+        if options.show_chars {
+            self.counts.chars += text.chars().count();
+        }
+        if let Some(graphemes) = &mut self.graphemes {
+            graphemes.feed(text);
+        }
+        if options.show_max_line_length {
+            for c in text.chars() {
+                match c {
+                    '\n' => {
+                        self.counts.max_line_length =
+                            self.counts.max_line_length.max(self.line_width);
+                        self.line_width = 0;
+                    }
+                    '\t' => self.line_width += TAB_WIDTH - self.line_width % TAB_WIDTH,
+                    // Control characters take no columns.
+                    c => self.line_width += c.width().unwrap_or(0),
+                }
+            }
+        }
+        for tokens in &mut self.tokens {
+            tokens.feed(text);
+        }
+        self.last_byte = Some(last_byte);
+    }
+
+    fn finish(mut self) -> Result<InputCounts, TokenizeError> {
+        if !self.options.show_bytes {
+            self.counts.bytes = 0;
+        }
+        if let Some(words) = self.segmented_words {
+            self.counts.words = words.finish();
+        }
+        if let Some(graphemes) = self.graphemes {
+            self.counts.graphemes = graphemes.finish();
+        }
+        if let Some(sentences) = self.sentences {
+            self.counts.sentences = sentences.finish();
+        }
+        self.counts.max_line_length = self.counts.max_line_length.max(self.line_width);
+        // A last line without a trailing newline still counts as a line.
+        if self.options.show_lines && self.last_byte.is_some_and(|b| b != b'\n') {
+            self.counts.lines += 1;
+        }
+        self.counts.tokens = self
+            .tokens
+            .into_iter()
+            .map(ChunkedTokenCount::finish)
+            .collect::<Result<_, _>>()?;
+        Ok(self.counts)
+    }
+}
+
+/// The kinds of segments counted by a [`SegmentCount`].
+#[derive(Clone, Copy)]
+enum Segment {
+    /// Words, in a word mode that looks at UAX #29 word boundaries.
+    Word(WordMode),
+    /// Extended grapheme clusters.
+    Grapheme,
+    /// Sentences of each paragraph, with its lines joined by spaces.
+    Sentence,
+}
+
+impl Segment {
+    /// Returns the last position in `text` where it can be split without
+    /// changing its segments, looking only at positions from `from` on.
+    fn boundary(self, text: &str, from: usize) -> Option<usize> {
+        match self {
+            Segment::Word(_) => word_boundary(text, from),
+            Segment::Grapheme => grapheme_boundary(text, from),
+            Segment::Sentence => paragraph_boundary(text, from).max(sentence_boundary(text, from)),
+        }
+    }
+
+    /// Returns the last position in `text` inside a segment where it can be
+    /// split, looking only at positions from `from` on. Both sides then
+    /// count that segment once.
+    fn inner_boundary(self, text: &str, from: usize) -> Option<usize> {
+        match self {
+            Segment::Sentence => letter_boundary(text, from),
+            _ => None,
+        }
+    }
+
+    fn count(self, text: &str) -> usize {
+        match self {
+            Segment::Word(WordMode::Cjk) => cjk::count_words(text),
+            Segment::Word(_) => text.unicode_words().count(),
+            Segment::Grapheme => text.graphemes(true).count(),
+            Segment::Sentence => split_paragraphs(text).map(count_sentences).sum(),
+        }
+    }
+}
+
+/// Counts the segments of text that is fed in pieces.
+///
+/// Text is buffered up to the last [`Segment::boundary`], so only the
+/// current line or run of characters without a boundary is held. A sentence
+/// longer than [`MAX_SENTENCE_SIZE`] is counted in pieces, split at
+/// [`Segment::inner_boundary`].
+struct SegmentCount {
+    segment: Segment,
+    pending: String,
+    count: usize,
+}
+
+impl SegmentCount {
+    fn new(segment: Segment) -> Self {
+        Self {
+            segment,
+            pending: String::new(),
+            count: 0,
+        }
+    }
+
+    fn feed(&mut self, text: &str) {
+        let scanned = self.pending.len();
+        self.pending.push_str(text);
+        if let Some(boundary) = self.segment.boundary(&self.pending, scanned) {
+            self.count += self.segment.count(&self.pending[..boundary]);
+            self.pending.drain(..boundary);
+        } else if self.pending.len() > MAX_SENTENCE_SIZE {
+            if let Some(boundary) = self.segment.inner_boundary(&self.pending, scanned) {
+                // The segment split at the boundary is counted again below.
+                self.count += self.segment.count(&self.pending[..boundary]) - 1;
+                self.pending.drain(..boundary);
+            }
+        }
+    }
+
+    fn finish(self) -> usize {
+        self.count + self.segment.count(&self.pending)
+    }
+}
+
+/// Returns the last position in `text` where it can be split without
+/// changing its words, looking only at positions from `from` on.
+///
+/// The split goes before a carriage return, before a newline that does not
+/// follow one, or before a space that does not follow another space. UAX #29
+/// always puts a word boundary there, and no rule looks back across it.
+fn word_boundary(text: &str, from: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    (from.max(1)..bytes.len()).rev().find(|&i| match bytes[i] {
+        b'\r' => true,
+        b'\n' => bytes[i - 1] != b'\r',
+        b' ' => bytes[i - 1] != b' ',
+        _ => false,
+    })
+}
+
+/// Returns the last position in `text` where it can be split without
+/// changing its grapheme clusters, looking only at positions from `from` on.
+///
+/// The split goes after a newline, before a newline or carriage return, or
+/// between two other ASCII characters, except inside a CRLF pair. UAX #29
+/// always puts a grapheme cluster boundary there, and no rule looks back
+/// across it.
+fn grapheme_boundary(text: &str, from: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    (from.max(1)..bytes.len()).rev().find(|&i| {
+        let (before, after) = (bytes[i - 1], bytes[i]);
+        if (before, after) == (b'\r', b'\n') {
+            return false;
+        }
+        before == b'\n'
+            || after == b'\n'
+            || after == b'\r'
+            || (before.is_ascii() && after.is_ascii())
+    })
+}
+
+/// Returns the last position in `text` where a paragraph starts, looking
+/// only at positions from `from` on.
+///
+/// A paragraph starts after a blank line, that is a line holding nothing but
+/// whitespace.
+fn paragraph_boundary(text: &str, from: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    (from.max(1)..=bytes.len())
+        .rev()
+        .find(|&i| ends_blank_line(bytes, i))
+}
+
+/// Returns whether the byte before `end` is a newline that ends a blank
+/// line.
+fn ends_blank_line(bytes: &[u8], end: usize) -> bool {
+    bytes[end - 1] == b'\n'
+        && bytes[..end - 1]
+            .iter()
+            .rev()
+            .find(|b| !matches!(b, b' ' | b'\t' | b'\r'))
+            == Some(&b'\n')
+}
+
+/// Returns the last position in `text` where a sentence starts for sure,
+/// looking only at positions from `from` on.
+///
+/// That is a Latin capital letter, or a CJK character, that follows a
+/// sentence terminator and any closing punctuation and spaces, with at
+/// least one space after a full stop. UAX #29 always puts a sentence
+/// boundary there, and no rule looks across the letter, so prose without
+/// blank lines is still counted a few sentences at a time.
+fn sentence_boundary(text: &str, from: usize) -> Option<usize> {
+    text.char_indices()
+        .rev()
+        .take_while(|&(i, _)| i >= from.max(1))
+        .find(|&(i, c)| {
+            (c.is_ascii_uppercase() || cjk::starts_sentence(c)) && follows_terminator(&text[..i])
+        })
+        .map(|(i, _)| i)
+}
+
+/// Returns the last position in `text` between two letters, looking only at
+/// positions from `from` on.
+///
+/// UAX #29 never puts a sentence boundary between two letters, and every
+/// rule that looks across characters stops at a letter, so each side of the
+/// split holds the same sentence boundaries as the whole text.
+fn letter_boundary(text: &str, from: usize) -> Option<usize> {
+    let mut chars = text.char_indices().rev();
+    let mut after = chars.next()?.1;
+    chars
+        .take_while(|&(i, c)| i + c.len_utf8() >= from.max(1))
+        .find_map(|(i, c)| {
+            let found = c.is_alphabetic() && after.is_alphabetic();
+            after = c;
+            found.then_some(i + c.len_utf8())
+        })
+}
+
+/// Returns whether `text` ends with a sentence terminator followed by
+/// closing punctuation and spaces.
+fn follows_terminator(text: &str) -> bool {
+    let mut chars = text.chars().rev().peekable();
+    let mut spaces = 0;
+    while chars
+        .next_if(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        .is_some()
+    {
+        spaces += 1;
+    }
+    while chars
+        .next_if(|c| matches!(c, '"' | '\'' | ')' | ']' | '」' | '』' | '）' | '”' | '’'))
+        .is_some()
+    {}
+    match chars.next() {
+        Some('!' | '?' | '。' | '！' | '？') => true,
+        // "U.S" is a single sentence, but not "U. S".
+        Some('.' | '．') => spaces > 0,
+        _ => false,
+    }
+}
+
+/// Splits `text` after every blank line, into paragraphs and the blank
+/// lines that follow them.
+fn split_paragraphs(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let bytes = rest.as_bytes();
+        let end = (1..=bytes.len())
+            .find(|&i| ends_blank_line(bytes, i))
+            .unwrap_or(bytes.len());
+        let (paragraph, next) = rest.split_at(end);
+        rest = next;
+        Some(paragraph)
+    })
+}
+
+/// Counts the sentences of a single paragraph.
+fn count_sentences(paragraph: &str) -> usize {
+    // UAX #29 ends a sentence at every line break, which would split
+    // sentences wrapped over several lines.
+    paragraph
+        .replace(['\r', '\n'], " ")
+        .unicode_sentences()
+        .count()
+}
+
+/// Counts tokens of text that is fed in pieces, without holding all of it.
+///
+/// Text is buffered until at least `chunk_size` bytes are pending, and then
+/// tokenized up to the last [`token_boundary`].
+struct ChunkedTokenCount<'a> {
+    tokenizer: &'a Tokenizer,
+    /// Where the text may be cut, for tokenizers that allow it.
+    cuts: Option<&'static Regex>,
+    chunk_size: usize,
+    pending: String,
+    /// Length of `pending` when it was last found to contain no boundary.
+    scanned: usize,
+    tokens: usize,
+    /// The first tokenization error, after which the rest is not tokenized.
+    error: Option<TokenizeError>,
+}
+
+impl<'a> ChunkedTokenCount<'a> {
+    fn new(tokenizer: &'a Tokenizer, chunk_size: usize) -> Self {
+        let cuts = match tokenizer {
+            Tokenizer::Model(model) => Some(token_cuts(*model)),
+            _ => None,
+        };
+        Self {
+            tokenizer,
+            cuts,
+            chunk_size,
+            pending: String::new(),
+            scanned: 0,
+            tokens: 0,
+            error: None,
+        }
+    }
+
+    fn feed(&mut self, text: &str) {
+        if self.error.is_some() {
+            return;
+        }
+        self.pending.push_str(text);
+        let Some(cuts) = self.cuts else {
+            return;
+        };
+        if self.pending.len() < self.chunk_size {
+            return;
+        }
+        // A cut may depend on the characters after the end of the text
+        // scanned, back to the character before its trailing newlines.
+        let from = self.pending[..self.scanned]
+            .trim_end_matches(['\r', '\n'])
+            .char_indices()
+            .next_back()
+            .map_or(0, |(i, _)| i);
+        match token_boundary(&self.pending, from, cuts) {
+            Some(boundary) => {
+                match self.tokenizer.count_tokens(&self.pending[..boundary]) {
+                    Ok(tokens) => self.tokens += tokens,
+                    Err(err) => self.error = Some(err),
+                }
+                self.pending.drain(..boundary);
+                self.scanned = 0;
+            }
+            None => self.scanned = self.pending.len(),
+        }
+    }
+
+    fn finish(self) -> Result<usize, TokenizeError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        Ok(self.tokens + self.tokenizer.count_tokens(&self.pending)?)
+    }
+}
+
+/// Returns the last position in `text` where it can be split without
+/// changing its token count, looking only at cuts from `from` on.
+///
+/// Each match of `cuts` allows a cut before its last character.
+fn token_boundary(text: &str, from: usize, cuts: &Regex) -> Option<usize> {
+    let mut boundary = None;
+    let mut start = from;
+    // Matches may overlap, so each search starts at the previous cut.
+    while let Some(cut) = cuts.find_at(text, start) {
+        let end = cut.end();
+        start = end - text[..end].chars().next_back().map_or(0, char::len_utf8);
+        boundary = Some(start);
+    }
+    boundary
+}
+
+/// Returns where text tokenized with `model` can be cut into pieces that
+/// tokenize exactly as they do within the whole text.
+///
+/// BPE merges never cross the pieces the pre-tokenizer pattern splits text
+/// into, and the patterns only look ahead, at most one character past the
+/// end of a run of whitespace. So a cut is safe between two characters
+/// when no piece can span them, the first not being whitespace:
+///
+/// - a letter before a number, whitespace, or punctuation other than a
+///   combining mark or an apostrophe, which `o200k_base` joins to letters;
+/// - a number before anything but a number;
+/// - punctuation before a number, or whitespace other than a newline.
+///
+/// Punctuation before a newline ends a piece in `r50k_base` and
+/// `p50k_base`, while `cl100k_base` and `o200k_base` take the newlines
+/// after it into its piece, and `o200k_base` slashes after those too. Those
+/// allow a cut after the newlines instead.
+fn token_cuts(model: TokenizerModel) -> &'static Regex {
+    const COMMON: &str =
+        r"\p{L}[\p{N}\s]|\p{L}[^\s\p{L}\p{N}\p{M}']|\p{N}\P{N}|[^\s\p{L}\p{N}][\p{N}[\s&&[^\r\n]]]";
+    static P50K: OnceLock<Regex> = OnceLock::new();
+    static CL100K: OnceLock<Regex> = OnceLock::new();
+    static O200K: OnceLock<Regex> = OnceLock::new();
+
+    let (regex, newlines) = match model {
+        TokenizerModel::GPT3 | TokenizerModel::Edit | TokenizerModel::Code => {
+            (&P50K, r"[^\s\p{L}\p{N}][\r\n]")
+        }
+        TokenizerModel::ChatGPT => (&CL100K, r"[^\s\p{L}\p{N}\p{M}][\r\n]+[^\r\n]"),
+        TokenizerModel::GPT4O => (&O200K, r"[^\s\p{L}\p{N}\p{M}][\r\n]+[^\r\n/]"),
+    };
+    regex.get_or_init(|| Regex::new(&format!("{COMMON}|{newlines}")).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::TokenizerModel;
+
+    /// A reader that hands out at most `step` bytes per read.
+    struct TrickleReader<'a> {
+        bytes: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for TrickleReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.step.min(buf.len()).min(self.bytes.len());
+            buf[..len].copy_from_slice(&self.bytes[..len]);
+            self.bytes = &self.bytes[len..];
+            Ok(len)
+        }
+    }
+
+    fn all_counts() -> Counter {
+        Counter::new(CountOptions {
+            show_lines: true,
+            show_words: true,
+            show_sentences: true,
+            show_paragraphs: true,
+            show_chars: true,
+            show_graphemes: true,
+            show_bytes: true,
+            show_max_line_length: true,
+            show_tokens: true,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
+        })
+    }
+
+    #[test]
+    fn test_reader_matches_lossy_decoding() {
+        let counter = all_counts();
+        let inputs: [&[u8]; 6] = [
+            "Hello 안녕 こんにちは World!\n🚀 Rust 💻\n".as_bytes(),
+            b"caf\xc3\xa9\n\xff\xfe broken \xe4\xb8 tail",
+            b"\xf0\x9f\x9a",
+            b"trailing newlines\n\n\n",
+            b"  spaced   out\twords \r\n",
+            b"",
+        ];
+        for input in inputs {
+            let text = String::from_utf8_lossy(input);
+            let expected = InputCounts {
+                bytes: input.len(),
+                ..counter.count_str(&text).unwrap()
+            };
+            assert_eq!(counter.count_bytes(input).unwrap(), expected);
+            for step in 1..=4 {
+                let reader = TrickleReader { bytes: input, step };
+                assert_eq!(counter.count_reader(reader).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reader_larger_than_buffer() {
+        let counter = all_counts();
+        let line = "héllo wörld 世界\n";
+        let text = line.repeat(BUFFER_SIZE / line.len() * 3 + 1);
+        let counts = counter.count_reader(text.as_bytes()).unwrap();
+        let lines = text.lines().count();
+        assert_eq!(counts.lines, lines);
+        assert_eq!(counts.words, lines * 3);
+        assert_eq!(counts.chars, text.chars().count());
+        assert_eq!(counts.bytes, text.len());
+        assert_eq!(counts.max_line_length, 16);
+    }
+
+    #[test]
+    fn test_max_line_length_display_width() {
+        let counter = all_counts();
+        let cases = [
+            ("", 0),
+            ("hello\nhi\n", 5),
+            ("世界\n", 4),
+            ("e\u{301}e\u{301}\n", 2),
+            ("\tx\n", 9),
+            ("ab\tc\n", 9),
+            ("1234567\t8", 9),
+            ("short\nno trailing newline", 19),
+            ("line\r\n", 4),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(
+                counter.count_str(text).unwrap().max_line_length,
+                expected,
+                "{text:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_graphemes() {
+        let counter = all_counts();
+        let cases = [
+            ("", 0),
+            ("abc", 3),
+            ("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}", 1),
+            ("e\u{301}", 1),
+            ("\u{1F1EF}\u{1F1F5}\u{1F1F0}", 2),
+            ("line\r\nline\n", 10),
+            ("한국어", 3),
+        ];
+        for (text, expected) in cases {
+            let counts = counter.count_str(text).unwrap();
+            assert_eq!(counts.graphemes, expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_graphemes_streamed() {
+        let counter = all_counts();
+        let text = "👨\u{200D}👩\u{200D}👧 family, cafe\u{301}\r\n🇯🇵🇰🇷 旗。\nक्षि\n".repeat(4);
+        let expected = text.graphemes(true).count();
+        for step in 1..=6 {
+            let reader = TrickleReader {
+                bytes: text.as_bytes(),
+                step,
+            };
+            assert_eq!(counter.count_reader(reader).unwrap().graphemes, expected);
+        }
+    }
+
+    #[test]
+    fn test_grapheme_boundary() {
+        assert_eq!(grapheme_boundary("abc", 0), Some(2));
+        assert_eq!(grapheme_boundary("a\r\n", 0), Some(1));
+        assert_eq!(grapheme_boundary("世界\n世界", 0), Some(7));
+        assert_eq!(grapheme_boundary("e\u{301}", 0), None);
+        assert_eq!(grapheme_boundary("ab世界", 2), None);
+    }
+
+    #[test]
+    fn test_sentences_and_paragraphs() {
+        let counter = all_counts();
+        let cases = [
+            ("", 0, 0),
+            ("One sentence", 1, 1),
+            ("It wraps\nover lines. Then ends!\n", 2, 1),
+            ("First.\n\nSecond. Third?\n  \n\n\tFourth\n", 4, 3),
+            ("你好。世界！\r\n\r\nこんにちは。", 3, 2),
+            ("\n \n...\n", 0, 1),
+        ];
+        for (text, sentences, paragraphs) in cases {
+            let counts = counter.count_str(text).unwrap();
+            assert_eq!(counts.sentences, sentences, "{text:?}");
+            assert_eq!(counts.paragraphs, paragraphs, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_sentences_streamed() {
+        let counter = all_counts();
+        let text = "A wrapped\nsentence. Another one!\n\nNew paragraph。次の文。\n \n".repeat(4);
+        for step in 1..=6 {
+            let reader = TrickleReader {
+                bytes: text.as_bytes(),
+                step,
+            };
+            let counts = counter.count_reader(reader).unwrap();
+            assert_eq!(counts.sentences, 16);
+            assert_eq!(counts.paragraphs, 8);
+        }
+    }
+
+    #[test]
+    fn test_sentences_of_unterminated_paragraphs_streamed() {
+        let counter = all_counts();
+        let text = "One\n\nTwo\n\nThree\n\nFour\n";
+        assert_eq!(counter.count_str(text).unwrap().sentences, 4);
+        for step in 1..=text.len() {
+            let reader = TrickleReader {
+                bytes: text.as_bytes(),
+                step,
+            };
+            assert_eq!(counter.count_reader(reader).unwrap().sentences, 4, "{step}");
+        }
+    }
+
+    #[test]
+    fn test_sentences_without_blank_lines_streamed() {
+        let counter = all_counts();
+        let text =
+            "It said \"Stop.\" Then it went on! U.S. forces met. 次の文。\nまた、次。\n".repeat(50);
+        let expected = counter.count_str(&text).unwrap().sentences;
+        assert_eq!(expected, 250);
+        for step in [1, 2, 3, 7, 64] {
+            let reader = TrickleReader {
+                bytes: text.as_bytes(),
+                step,
+            };
+            assert_eq!(counter.count_reader(reader).unwrap().sentences, expected);
+        }
+
+        let mut sentences = SegmentCount::new(Segment::Sentence);
+        for line in text.split_inclusive('\n') {
+            sentences.feed(line);
+            assert!(sentences.pending.len() < 40, "{:?}", sentences.pending);
+        }
+    }
+
+    #[test]
+    fn test_sentence_boundary() {
+        assert_eq!(sentence_boundary("One. Two", 0), Some(5));
+        assert_eq!(sentence_boundary("Hi!\" Yes", 0), Some(5));
+        assert_eq!(sentence_boundary("你好。世界", 0), Some(9));
+        assert_eq!(sentence_boundary("U.S. forces", 0), None);
+        assert_eq!(sentence_boundary("U.S.Army", 0), None);
+        assert_eq!(sentence_boundary("etc. and more", 0), None);
+        assert_eq!(sentence_boundary("One. Two", 6), None);
+    }
+
+    #[test]
+    fn test_long_sentences_streamed() {
+        let counter = all_counts();
+        let text = "info: request served in 12 ms. user logged out.
+"
+        .repeat(4000);
+        let expected = counter.count_str(&text).unwrap().sentences;
+        assert_eq!(expected, 1);
+        let reader = TrickleReader {
+            bytes: text.as_bytes(),
+            step: 7,
+        };
+        assert_eq!(counter.count_reader(reader).unwrap().sentences, expected);
+
+        let mut sentences = SegmentCount::new(Segment::Sentence);
+        for line in text.split_inclusive('\n') {
+            sentences.feed(line);
+            assert!(sentences.pending.len() <= MAX_SENTENCE_SIZE + line.len());
+        }
+        assert_eq!(sentences.finish(), expected);
+    }
+
+    #[test]
+    fn test_letter_boundary() {
+        assert_eq!(letter_boundary("ab. c", 0), Some(1));
+        assert_eq!(letter_boundary("aé. 1", 0), Some(1));
+        assert_eq!(letter_boundary("a. b", 0), None);
+        assert_eq!(letter_boundary("ab cd", 3), Some(4));
+        assert_eq!(letter_boundary("ab cd", 5), None);
+    }
+
+    #[test]
+    fn test_paragraph_boundary() {
+        assert_eq!(paragraph_boundary("a\n\nb", 0), Some(3));
+        assert_eq!(paragraph_boundary("a\n \t\r\nb\n", 0), Some(6));
+        assert_eq!(paragraph_boundary("a\n\n", 0), Some(3));
+        assert_eq!(paragraph_boundary("a\nb\n", 0), None);
+        assert_eq!(paragraph_boundary("a\n\nb\nc", 4), None);
+    }
+
+    fn unicode_words() -> Counter {
+        Counter::new(CountOptions {
+            show_words: true,
+            word_mode: WordMode::Unicode,
+            ..CountOptions::default()
+        })
+    }
+
+    #[test]
+    fn test_unicode_words() {
+        let counter = unicode_words();
+        let cases = [
+            ("", 0),
+            ("hello\u{a0}world\u{3000}foo", 3),
+            ("Hello, world! — it's 3.14", 4),
+            ("こんにちは 世界！", 7),
+            ("テキスト", 1),
+            ("... !!! ---\n", 0),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(counter.count_str(text).unwrap().words, expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_unicode_words_streamed() {
+        let counter = unicode_words();
+        let text = "Hello,  world!\r\nこんにちは 世界。\n\tcan't stop  won't stop 🚀\n".repeat(5);
+        let expected = text.unicode_words().count();
+        for step in 1..=7 {
+            let reader = TrickleReader {
+                bytes: text.as_bytes(),
+                step,
+            };
+            assert_eq!(counter.count_reader(reader).unwrap().words, expected);
+        }
+    }
+
+    #[test]
+    fn test_cjk_words_streamed() {
+        let counter = Counter::new(CountOptions {
+            word_mode: WordMode::Cjk,
+            ..unicode_words().options
+        });
+        let text = "我来到北京清华大学。\nRust 是一门编程语言！\n".repeat(3);
+        assert_eq!(counter.count_str(&text).unwrap().words, 24);
+        for step in 1..=5 {
+            let reader = TrickleReader {
+                bytes: text.as_bytes(),
+                step,
+            };
+            assert_eq!(counter.count_reader(reader).unwrap().words, 24);
+        }
+    }
+
+    #[test]
+    fn test_word_boundary() {
+        assert_eq!(word_boundary("ab cd", 0), Some(2));
+        assert_eq!(word_boundary("ab  cd", 0), Some(2));
+        assert_eq!(word_boundary("ab\r\ncd", 0), Some(2));
+        assert_eq!(word_boundary("ab\ncd ef", 4), Some(5));
+        assert_eq!(word_boundary("ab\ncd", 3), None);
+        assert_eq!(word_boundary(" abc", 0), None);
+    }
+
+    const MODELS: [TokenizerModel; 5] = [
+        TokenizerModel::GPT3,
+        TokenizerModel::Edit,
+        TokenizerModel::Code,
+        TokenizerModel::ChatGPT,
+        TokenizerModel::GPT4O,
+    ];
+
+    /// Counts tokens of `text` by feeding it in `step`-byte pieces, also
+    /// returning the most text that was pending at once.
+    fn chunked_tokens(
+        model: TokenizerModel,
+        text: &str,
+        step: usize,
+        chunk_size: usize,
+    ) -> (usize, usize) {
+        let tokenizer = Tokenizer::from(model);
+        let mut tokens = ChunkedTokenCount::new(&tokenizer, chunk_size);
+        let mut max_pending = 0;
+        let mut rest = text;
+        while !rest.is_empty() {
+            let mut end = step.min(rest.len());
+            while !rest.is_char_boundary(end) {
+                end += 1;
+            }
+            tokens.feed(&rest[..end]);
+            max_pending = max_pending.max(tokens.pending.len());
+            rest = &rest[end..];
+        }
+        (tokens.finish().unwrap(), max_pending)
+    }
+
+    fn assert_chunked_tokens_match(text: &str) {
+        for model in MODELS {
+            let expected = model.bpe().encode_ordinary(text).len();
+            for (step, chunk_size) in [(1, 1), (3, 8), (7, 16), (64, 64)] {
+                assert_eq!(
+                    chunked_tokens(model, text, step, chunk_size).0,
+                    expected,
+                    "{:?} step {} chunk {}: {:?}",
+                    model,
+                    step,
+                    chunk_size,
+                    text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_token_boundary() {
+        let boundary = |model, text| token_boundary(text, 0, token_cuts(model));
+        for model in MODELS {
+            assert_eq!(boundary(model, "hello\nworld"), Some(5));
+            assert_eq!(boundary(model, "a b"), Some(1));
+            assert_eq!(boundary(model, "ab12+"), Some(4));
+            assert_eq!(boundary(model, "x=1"), Some(2));
+            assert_eq!(boundary(model, "don't"), None);
+            assert_eq!(boundary(model, "e\u{301}"), None);
+            assert_eq!(boundary(model, "hello \nworld"), Some(5));
+            assert_eq!(boundary(model, " \n\t "), None);
+            assert_eq!(boundary(model, "\"hi"), None);
+            assert_eq!(boundary(model, "world"), None);
+        }
+        // Punctuation keeps the newlines after it outside of GPT-3.
+        let text = "{\n\n    x";
+        assert_eq!(boundary(TokenizerModel::GPT3, text), Some(1));
+        assert_eq!(boundary(TokenizerModel::ChatGPT, text), Some(3));
+        assert_eq!(boundary(TokenizerModel::GPT4O, text), Some(3));
+        assert_eq!(boundary(TokenizerModel::GPT4O, "}\n/"), None);
+        let cuts = token_cuts(TokenizerModel::GPT3);
+        assert_eq!(token_boundary("a b c", 2, cuts), Some(3));
+    }
+
+    #[test]
+    fn test_chunked_tokens_match_whole_text() {
+        let texts = [
+            "Hello, world!\nThis is a test.",
+            "Hello 안녕 こんにちは World!\n🚀 Rust 💻\n世界です\n",
+            "fn main() {\n    println!(\"{}\", 12345678);\n}\n",
+            "trailing spaces   \nand\ttabs\t\nnext\n\n\nblank lines\n",
+            "windows\r\nline\r\nendings\r\n",
+            "slashes/\n/and/\npunctuation...\n!!!\nwords\n",
+            "digits 1234567\n890123\n4567 end\n",
+            "contractions\n's and\n'll and\nIT'S\nDON'T\n",
+            "combining e\u{301}\n\u{301}mark\nÉcole\n",
+            "",
+        ];
+        for text in texts {
+            assert_chunked_tokens_match(text);
+        }
+    }
+
+    #[test]
+    fn test_chunked_tokens_match_generated_text() {
+        let alphabet: Vec<char> = "ab Z9 \n\n\r\t.,'/-世界é\u{301}🚀".chars().collect();
+        let mut seed: u64 = 42;
+        for _ in 0..20 {
+            let text: String = (0..400)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    alphabet[(seed >> 33) as usize % alphabet.len()]
+                })
+                .collect();
+            assert_chunked_tokens_match(&text);
+        }
+
+        // Indented code and pretty-printed JSON, whose lines all start with
+        // spaces, are still cut within a few lines.
+        let pieces = [
+            "    ",
+            "\t",
+            "\n",
+            "\r\n",
+            "{",
+            "}",
+            "\"key\": ",
+            "[1, 2],",
+            "foo(bar);",
+            "// note",
+            "x = y / 2;",
+            "</div>",
+        ];
+        let text: String = (0..4000)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                pieces[(seed >> 33) as usize % pieces.len()]
+            })
+            .collect();
+        for model in MODELS {
+            let (tokens, max_pending) = chunked_tokens(model, &text, 64, 64);
+            assert_eq!(tokens, model.bpe().encode_ordinary(&text).len());
+            assert!(max_pending < 256, "{model:?} held {max_pending} bytes");
+        }
+    }
+
+    #[test]
+    fn test_reader_tokens_match_whole_text() {
+        let text = "The quick brown fox\njumps over the lazy dog.\n".repeat(BUFFER_SIZE / 20);
+        for model in MODELS {
+            let counter = Counter::new(CountOptions {
+                show_tokens: true,
+                tokenizers: vec![model.into()],
+                ..CountOptions::default()
+            });
+            let expected = model.bpe().encode_ordinary(&text).len();
+            assert_eq!(
+                counter.count_reader(text.as_bytes()).unwrap().tokens,
+                [expected]
+            );
+        }
+    }
+}
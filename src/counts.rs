@@ -1,35 +1,114 @@
-use crate::cmd::TokenizerModel;
+use crate::tokenizer::Tokenizer;
+use clap::ValueEnum;
 
+/// Selects which counts a [`Counter`](crate::Counter) computes.
+///
+/// Counts that are not enabled are left at zero in the resulting
+/// [`InputCounts`]. The default enables no counts and has no tokenizers;
+/// fields may be added, so set the ones you need on
+/// [`CountOptions::default`].
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
 pub struct CountOptions {
+    /// Whether [`InputCounts::lines`] is counted.
     pub show_lines: bool,
+    /// Whether [`InputCounts::words`] is counted.
     pub show_words: bool,
+    /// Whether [`InputCounts::sentences`] is counted.
+    pub show_sentences: bool,
+    /// Whether [`InputCounts::paragraphs`] is counted.
+    pub show_paragraphs: bool,
+    /// Whether [`InputCounts::chars`] is counted.
     pub show_chars: bool,
+    /// Whether [`InputCounts::graphemes`] is counted.
+    pub show_graphemes: bool,
+    /// Whether [`InputCounts::bytes`] is counted.
+    pub show_bytes: bool,
+    /// Whether [`InputCounts::max_line_length`] is counted.
+    pub show_max_line_length: bool,
+    /// Whether [`InputCounts::tokens`] is counted, with each of the
+    /// [`tokenizers`](Self::tokenizers).
     pub show_tokens: bool,
-    pub tokenizer_model: TokenizerModel,
+    /// How the input is split into words.
+    pub word_mode: WordMode,
+    /// The tokenizers to count tokens with, one count per tokenizer.
+    pub tokenizers: Vec<Tokenizer>,
 }
 
 impl CountOptions {
+    /// Returns how many counts are enabled.
     pub fn count_enabled_options(&self) -> u8 {
         self.show_lines as u8
             + self.show_words as u8
+            + self.show_sentences as u8
+            + self.show_paragraphs as u8
             + self.show_chars as u8
+            + self.show_graphemes as u8
+            + self.show_bytes as u8
+            + self.show_max_line_length as u8
             + self.show_tokens as u8
     }
 }
 
-#[derive(Default)]
+/// The ways of splitting text into words.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum WordMode {
+    /// Words are separated by ASCII whitespace, like `wc` does.
+    #[default]
+    Ascii,
+    /// Words are found at UAX #29 word boundaries, ignoring segments made
+    /// only of punctuation or spaces.
+    Unicode,
+    /// Like `unicode`, but runs of Han characters are cut into words with a
+    /// bundled Chinese dictionary. There is no Japanese dictionary: runs
+    /// mixing Kanji and Hiragana are only cut where the script changes, and
+    /// Korean is split as in `unicode`.
+    Cjk,
+}
+
+/// The counts computed for a single input.
+///
+/// Counts of several inputs can be summed with `+=`, which keeps the larger
+/// of the two maximum line lengths.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct InputCounts {
+    /// Number of lines, including a last line without a trailing newline.
     pub lines: usize,
+    /// Number of words, split according to [`CountOptions::word_mode`].
     pub words: usize,
+    /// Number of sentences, found at UAX #29 sentence boundaries within each
+    /// paragraph.
+    pub sentences: usize,
+    /// Number of blocks of lines separated by blank lines.
+    pub paragraphs: usize,
+    /// Number of Unicode scalar values, with each invalid UTF-8 sequence
+    /// counted as one U+FFFD replacement character.
     pub chars: usize,
-    pub tokens: usize,
+    /// Number of extended grapheme clusters, the characters a user sees.
+    pub graphemes: usize,
+    /// Raw length of the input in bytes, before any UTF-8 decoding.
+    pub bytes: usize,
+    /// Display width of the widest line, in terminal columns.
+    pub max_line_length: usize,
+    /// Token counts in the order of [`CountOptions::tokenizers`].
+    pub tokens: Vec<usize>,
 }
 
 impl std::ops::AddAssign for InputCounts {
     fn add_assign(&mut self, other: Self) {
         self.lines += other.lines;
         self.words += other.words;
+        self.sentences += other.sentences;
+        self.paragraphs += other.paragraphs;
         self.chars += other.chars;
-        self.tokens += other.tokens;
+        self.graphemes += other.graphemes;
+        self.bytes += other.bytes;
+        self.max_line_length = self.max_line_length.max(other.max_line_length);
+        if self.tokens.len() < other.tokens.len() {
+            self.tokens.resize(other.tokens.len(), 0);
+        }
+        for (total, tokens) in self.tokens.iter_mut().zip(other.tokens) {
+            *total += tokens;
+        }
     }
 }
@@ -1,200 +1,272 @@
-use crate::cmd::TokenizerModel;
-use crate::counts::{CountOptions, InputCounts};
+use crate::counter::{CountError, Counter};
+use crate::counts::InputCounts;
+use crate::output::{Output, OutputFormat};
+use crate::tokenizer::TokenizeError;
+use crate::walk::{self, WalkOptions};
+use clap::ValueEnum;
 use rust_i18n::t;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs::File;
-use std::io::{self, BufReader, Read, Write};
+use std::io::{self, BufReader, Write};
 use std::path::Path;
-use tiktoken_rs::{cl100k_base, o200k_base, p50k_base, p50k_edit, r50k_base};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// When the total row is written.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum TotalMode {
+    /// When more than one file is counted.
+    #[default]
+    Auto,
+    /// Always, even for a single input.
+    Always,
+    /// Only the total, without a row per input.
+    Only,
+    /// Never.
+    Never,
+}
+
+pub struct ProcessOptions {
+    /// Number of files counted concurrently.
+    pub jobs: usize,
+    pub format: OutputFormat,
+    pub total: TotalMode,
+    /// Whether text output starts with a row of column names.
+    pub header: bool,
+    /// Names of the tokenizers, in the order of their token columns.
+    pub tokenizer_names: Vec<String>,
+    pub walk: WalkOptions,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        Self {
+            jobs: thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+            format: OutputFormat::default(),
+            total: TotalMode::default(),
+            header: false,
+            tokenizer_names: Vec::new(),
+            walk: WalkOptions::default(),
+        }
+    }
+}
 
-pub fn process_inputs<W>(files: &[String], writer: &mut W, options: &CountOptions) -> io::Result<()>
+enum InputError {
+    IsADirectory,
+    Open(io::Error),
+    Read(io::Error),
+    Tokenize(TokenizeError),
+    Walk(ignore::Error),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::IsADirectory => write!(f, "Is a directory"),
+            InputError::Open(err) | InputError::Read(err) => write!(f, "{}", err),
+            InputError::Tokenize(err) => write!(f, "{}", err),
+            InputError::Walk(err) => match err.io_error() {
+                Some(err) => write!(f, "{}", err),
+                None => write!(f, "{}", err),
+            },
+        }
+    }
+}
+
+pub fn process_inputs<W>(
+    files: &[String],
+    writer: &mut W,
+    counter: &Counter,
+    process_options: &ProcessOptions,
+) -> io::Result<()>
 where
     W: Write,
 {
+    let mut output = Output::new(writer, counter.options(), process_options);
     let mut total_counts = InputCounts::default();
     let mut file_count = 0;
     let mut error_count = 0;
+    let show_inputs = process_options.total != TotalMode::Only;
+    output.header()?;
 
     if files.is_empty() {
         let stdin = io::stdin();
-        let mut reader = BufReader::new(stdin.lock());
-        if let Err(err) = process_input(&mut reader, writer, options, None) {
-            error_count += 1;
-            match err.kind() {
-                io::ErrorKind::WriteZero => {
+        let reader = BufReader::new(stdin.lock());
+        match counter.count_reader(reader) {
+            Ok(counts) => {
+                if show_inputs && output.counts(None, &counts).is_err() {
+                    error_count += 1;
                     eprintln!("{}", t!("error_writing_stdout"));
+                } else {
+                    total_counts += counts;
                 }
-                _ => {
-                    eprintln!("{}", t!("error_reading_stdin"));
+            }
+            Err(err) => {
+                error_count += 1;
+                match &err {
+                    CountError::Read(_) => eprintln!("{}", t!("error_reading_stdin")),
+                    CountError::Tokenize(err) => {
+                        eprintln!(
+                            "{}",
+                            t!("error_counting_tokens", filename = "-", error = err)
+                        );
+                    }
                 }
+                output.error(None, &err.to_string())?;
             }
         }
     } else {
-        for filename in files {
-            if Path::new(&filename).is_dir() {
-                error_count += 1;
-                eprintln!("{}", t!("error_is_a_directory", filename = filename));
-                continue;
+        // Files are counted on a pool of workers, but results are reported
+        // in command line order so the output does not depend on scheduling.
+        // Streamed formats report each file as soon as it is counted instead.
+        let in_order = !process_options.format.is_streamed();
+        let inputs = walk::expand_inputs(files, &process_options.walk);
+        let jobs = process_options.jobs.min(inputs.len()).max(1);
+        let next_file = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+
+        thread::scope(|scope| -> io::Result<()> {
+            for _ in 0..jobs {
+                let sender = sender.clone();
+                let next_file = &next_file;
+                let inputs = &inputs;
+                scope.spawn(move || loop {
+                    let index = next_file.fetch_add(1, Ordering::Relaxed);
+                    let Some(input) = inputs.get(index) else {
+                        break;
+                    };
+                    let result = match &input.error {
+                        Some(err) => Err(InputError::Walk(err.clone())),
+                        None => count_file(&input.name, counter),
+                    };
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                });
             }
-            match File::open(filename) {
-                Ok(mut file) => match process_input(&mut file, writer, options, Some(filename)) {
-                    Ok(counts) => {
-                        total_counts += counts;
+            drop(sender);
+
+            let mut pending = BTreeMap::new();
+            let mut next_to_report = 0;
+            for (index, result) in receiver {
+                pending.insert(index, result);
+                while let Some(entry) = pending.first_entry() {
+                    if in_order && *entry.key() != next_to_report {
+                        break;
                     }
-                    Err(err) => {
-                        error_count += 1;
-                        match err.kind() {
-                            io::ErrorKind::WriteZero => {
+                    let (index, result) = entry.remove_entry();
+                    let filename = &inputs[index].name;
+                    next_to_report += 1;
+                    match result {
+                        Ok(counts) => {
+                            if show_inputs && output.counts(Some(filename), &counts).is_err() {
+                                error_count += 1;
                                 eprintln!("{}", t!("error_writing_stdout"));
+                            } else {
+                                total_counts += counts;
                             }
-                            _ => {
-                                eprintln!("{}", t!("error_reading_file", filename = filename));
-                            }
-                        }
-                    }
-                },
-                Err(err) => {
-                    error_count += 1;
-                    match err.kind() {
-                        io::ErrorKind::NotFound => {
-                            eprintln!("{}", t!("error_not_found", filename = filename));
                         }
-                        io::ErrorKind::PermissionDenied => {
-                            eprintln!("{}", t!("error_permission_denied", filename = filename));
-                        }
-                        _ => {
-                            eprintln!(
-                                "{}",
-                                t!(
-                                    "error_opening_file",
-                                    filename = filename,
-                                    error = err.kind()
-                                )
-                            );
+                        Err(err) => {
+                            error_count += 1;
+                            report_error(filename, &err);
+                            output.error(Some(filename), &err.to_string())?;
+                            if matches!(err, InputError::IsADirectory) {
+                                continue;
+                            }
                         }
                     }
+                    file_count += 1;
                 }
             }
-            file_count += 1;
-        }
+            Ok(())
+        })?;
+    }
+    let show_total = match process_options.total {
+        TotalMode::Auto => file_count > 1,
+        TotalMode::Always | TotalMode::Only => true,
+        TotalMode::Never => false,
+    };
+    if show_total {
+        output.total(&total_counts)?;
     }
-    if file_count > 1 {
-        print_counts(
-            writer,
-            &total_counts,
-            options,
-            Some(&format!("{}", t!("total"))),
-        )?;
+    if output.finish().is_err() {
+        error_count += 1;
+        eprintln!("{}", t!("error_writing_stdout"));
     }
 
     if error_count > 0 {
-        Err(io::Error::new(io::ErrorKind::Other, ""))
+        Err(io::Error::other(""))
     } else {
         Ok(())
     }
 }
 
-fn process_input<R, W>(
-    reader: &mut R,
-    writer: &mut W,
-    options: &CountOptions,
-    filename: Option<&str>,
-) -> io::Result<InputCounts>
-where
-    R: Read,
-    W: Write,
-{
-    let mut buffer = Vec::new();
-    reader.read_to_end(&mut buffer)?;
-
-    let counts = count_input(&buffer, options);
-    print_counts(writer, &counts, options, filename)?;
-    Ok(counts)
+fn count_file(filename: &str, counter: &Counter) -> Result<InputCounts, InputError> {
+    if Path::new(filename).is_dir() {
+        return Err(InputError::IsADirectory);
+    }
+    let file = File::open(filename).map_err(InputError::Open)?;
+    counter.count_reader(file).map_err(|err| match err {
+        CountError::Read(err) => InputError::Read(err),
+        CountError::Tokenize(err) => InputError::Tokenize(err),
+    })
 }
 
-fn count_input(buffer: &[u8], options: &CountOptions) -> InputCounts {
-    let buffer_string = String::from_utf8_lossy(buffer);
-    let mut line_count = 0;
-    let mut word_count = 0;
-    let mut char_count = 0;
-    let mut token_count = 0;
-
-    let lines = buffer.split(|&b| b == b'\n');
-    let mut lines_iter = lines.peekable();
-
-    while let Some(line) = lines_iter.next() {
-        if lines_iter.peek().is_none() && line.is_empty() {
-            break;
+fn report_error(filename: &str, err: &InputError) {
+    match err {
+        InputError::IsADirectory => {
+            eprintln!("{}", t!("error_is_a_directory", filename = filename));
         }
-        if options.show_lines {
-            line_count += 1;
+        InputError::Read(_) => {
+            eprintln!("{}", t!("error_reading_file", filename = filename));
         }
-        if options.show_words {
-            word_count += line
-                .split(|&b| b.is_ascii_whitespace())
-                .filter(|&w| !w.is_empty())
-                .count();
+        InputError::Tokenize(err) => {
+            eprintln!(
+                "{}",
+                t!("error_counting_tokens", filename = filename, error = err)
+            );
         }
-    }
-
-    if options.show_chars {
-        char_count = buffer_string.chars().count();
-    }
-
-    if options.show_tokens {
-        let tokenizer = match options.tokenizer_model {
-            TokenizerModel::GPT3 => r50k_base().unwrap(),
-            TokenizerModel::Edit => p50k_edit().unwrap(),
-            TokenizerModel::Code => p50k_base().unwrap(),
-            TokenizerModel::ChatGPT => cl100k_base().unwrap(),
-            TokenizerModel::GPT4O => o200k_base().unwrap(),
-        };
-        token_count = tokenizer.encode_ordinary(&buffer_string).len();
-    }
-
-    InputCounts {
-        lines: line_count,
-        words: word_count,
-        chars: char_count,
-        tokens: token_count,
+        InputError::Open(err) => report_open_error(filename, err),
+        InputError::Walk(err) => match err.io_error() {
+            Some(err) => report_open_error(filename, err),
+            None => {
+                eprintln!(
+                    "{}",
+                    t!("error_opening_file", filename = filename, error = err)
+                );
+            }
+        },
     }
 }
 
-fn print_counts<W: Write>(
-    writer: &mut W,
-    counts: &InputCounts,
-    options: &CountOptions,
-    filename: Option<&str>,
-) -> io::Result<()> {
-    let mut output = String::new();
-    const FORMAT_LEN: usize = 8;
-
-    if options.show_lines {
-        output.push_str(&format!("{: >width$} ", counts.lines, width = FORMAT_LEN));
-    }
-    if options.show_words {
-        output.push_str(&format!("{: >width$} ", counts.words, width = FORMAT_LEN));
-    }
-    if options.show_chars {
-        output.push_str(&format!("{: >width$} ", counts.chars, width = FORMAT_LEN));
-    }
-    if options.show_tokens {
-        output.push_str(&format!("{: >width$} ", counts.tokens, width = FORMAT_LEN));
-    }
-
-    if let Some(name) = filename {
-        output.push_str(name);
-    }
-
-    if writeln!(writer, "{}", output.trim_end()).is_err() {
-        Err(io::Error::new(io::ErrorKind::WriteZero, ""))
-    } else {
-        Ok(())
+fn report_open_error(filename: &str, err: &io::Error) {
+    match err.kind() {
+        io::ErrorKind::NotFound => {
+            eprintln!("{}", t!("error_not_found", filename = filename));
+        }
+        io::ErrorKind::PermissionDenied => {
+            eprintln!("{}", t!("error_permission_denied", filename = filename));
+        }
+        _ => {
+            eprintln!(
+                "{}",
+                t!(
+                    "error_opening_file",
+                    filename = filename,
+                    error = err.kind()
+                )
+            );
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::counts::CountOptions;
+    use crate::tokenizer::TokenizerModel;
     use tempfile::NamedTempFile;
 
     #[test]
@@ -205,22 +277,19 @@ mod tests {
             show_lines: true,
             show_words: true,
             show_chars: true,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             output,
-            format!(
-                "       0        0        0 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
-            .as_bytes()
+            format!("0 0 0 {}\n", temp_file.path().to_str().unwrap()).as_bytes()
         );
         temp_file.close().unwrap();
     }
@@ -234,21 +303,19 @@ mod tests {
             show_lines: true,
             show_words: true,
             show_chars: true,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!(
-                "       1        1        5 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
+            format!("1 1 5 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -262,21 +329,19 @@ mod tests {
             show_lines: true,
             show_words: true,
             show_chars: true,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!(
-                "       2        5       25 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
+            format!(" 2  5 25 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -288,20 +353,19 @@ mod tests {
         let mut output = Vec::new();
         let options = CountOptions {
             show_lines: true,
-            show_words: false,
-            show_chars: false,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!("       2 {}\n", temp_file.path().to_str().unwrap())
+            format!("2 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -312,21 +376,20 @@ mod tests {
         temp_file.write_all(b"hello world rust").unwrap();
         let mut output = Vec::new();
         let options = CountOptions {
-            show_lines: false,
             show_words: true,
-            show_chars: false,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!("       3 {}\n", temp_file.path().to_str().unwrap())
+            format!("3 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -337,21 +400,20 @@ mod tests {
         temp_file.write_all(b"hello\n").unwrap();
         let mut output = Vec::new();
         let options = CountOptions {
-            show_lines: false,
-            show_words: false,
             show_chars: true,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!("       6 {}\n", temp_file.path().to_str().unwrap())
+            format!("6 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -365,21 +427,19 @@ mod tests {
             show_lines: true,
             show_words: true,
             show_chars: true,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!(
-                "       1        2       11 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
+            format!(" 1  2 11 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -393,21 +453,19 @@ mod tests {
             show_lines: true,
             show_words: true,
             show_chars: true,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!(
-                "       1        3        9 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
+            format!("1 3 9 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -423,21 +481,19 @@ mod tests {
             show_lines: true,
             show_words: true,
             show_chars: true,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!(
-                "       1        2       10 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
+            format!(" 1  2 10 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -453,21 +509,19 @@ mod tests {
             show_lines: true,
             show_words: true,
             show_chars: true,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!(
-                "       1        2       10 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
+            format!(" 1  2 10 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -483,21 +537,19 @@ mod tests {
             show_lines: true,
             show_words: true,
             show_chars: true,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!(
-                "       1        4       22 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
+            format!(" 1  4 22 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -513,21 +565,19 @@ mod tests {
             show_lines: true,
             show_words: true,
             show_chars: true,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!(
-                "       2        2       12 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
+            format!(" 2  2 12 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -543,21 +593,19 @@ mod tests {
             show_lines: true,
             show_words: true,
             show_chars: true,
-            show_tokens: false,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!(
-                "       2        2       11 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
+            format!(" 2  2 11 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -570,21 +618,20 @@ mod tests {
             .unwrap();
         let mut output = Vec::new();
         let options = CountOptions {
-            show_lines: false,
-            show_words: false,
-            show_chars: false,
             show_tokens: true,
-            tokenizer_model: TokenizerModel::Edit,
+            tokenizers: vec![TokenizerModel::Edit.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!("      11 {}\n", temp_file.path().to_str().unwrap())
+            format!("11 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -601,20 +648,19 @@ mod tests {
             show_words: true,
             show_chars: true,
             show_tokens: true,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!(
-                "       2        6       29       10 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
+            format!(" 2  6 29 10 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -625,42 +671,40 @@ mod tests {
         temp_file.write_all(b"Hello, world!").unwrap();
         let mut output = Vec::new();
         let options = CountOptions {
-            show_lines: false,
-            show_words: false,
-            show_chars: false,
             show_tokens: true,
-            tokenizer_model: TokenizerModel::GPT3,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!("       4 {}\n", temp_file.path().to_str().unwrap())
+            format!("4 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
         let mut temp_file = NamedTempFile::new().unwrap();
         temp_file.write_all(b"Hello, world!").unwrap();
         let mut output = Vec::new();
         let options = CountOptions {
-            show_lines: false,
-            show_words: false,
-            show_chars: false,
             show_tokens: true,
-            tokenizer_model: TokenizerModel::GPT4O,
+            tokenizers: vec![TokenizerModel::GPT4O.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!("       4 {}\n", temp_file.path().to_str().unwrap())
+            format!("4 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
@@ -677,21 +721,76 @@ mod tests {
             show_words: true,
             show_chars: true,
             show_tokens: true,
-            tokenizer_model: TokenizerModel::GPT4O,
+            tokenizers: vec![TokenizerModel::GPT4O.into()],
+            ..CountOptions::default()
         };
         process_inputs(
             &[temp_file.path().to_str().unwrap().to_string()],
             &mut output,
-            &options,
+            &Counter::new(options),
+            &ProcessOptions::default(),
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(output).unwrap(),
-            format!(
-                "       2        6       29        9 {}\n",
-                temp_file.path().to_str().unwrap()
-            )
+            format!(" 2  6 29  9 {}\n", temp_file.path().to_str().unwrap())
         );
         temp_file.close().unwrap();
     }
+
+    #[test]
+    fn test_parallel_output_order() {
+        let temp_files: Vec<NamedTempFile> = (1..=8)
+            .map(|words| {
+                let mut temp_file = NamedTempFile::new().unwrap();
+                temp_file
+                    .write_all("word ".repeat(words * 1000).as_bytes())
+                    .unwrap();
+                temp_file
+            })
+            .collect();
+        let files: Vec<String> = temp_files
+            .iter()
+            .map(|temp_file| temp_file.path().to_str().unwrap().to_string())
+            .collect();
+        let options = CountOptions {
+            show_words: true,
+            show_tokens: true,
+            tokenizers: vec![TokenizerModel::GPT3.into()],
+            ..CountOptions::default()
+        };
+        let counter = Counter::new(options);
+
+        let mut sequential = Vec::new();
+        process_inputs(
+            &files,
+            &mut sequential,
+            &counter,
+            &ProcessOptions {
+                jobs: 1,
+                ..ProcessOptions::default()
+            },
+        )
+        .unwrap();
+        let mut parallel = Vec::new();
+        process_inputs(
+            &files,
+            &mut parallel,
+            &counter,
+            &ProcessOptions {
+                jobs: 4,
+                ..ProcessOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(sequential, parallel);
+        let output = String::from_utf8(parallel).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), files.len() + 1);
+        for (line, filename) in lines.iter().zip(&files) {
+            assert!(line.ends_with(filename.as_str()));
+        }
+        assert!(lines[files.len()].starts_with("36000 "));
+    }
 }
@@ -0,0 +1,42 @@
+//! A simple and efficient token count library.
+//!
+//! `tc` counts lines, words, characters and tokens in text. The same logic
+//! that powers the `tc` binary is available here through [`Counter`]:
+//!
+//! ```
+//! use tc::{CountOptions, Counter, TokenizerModel};
+//!
+//! let mut options = CountOptions::default();
+//! options.show_lines = true;
+//! options.show_words = true;
+//! options.show_chars = true;
+//! options.show_tokens = true;
+//! options.tokenizers = vec![TokenizerModel::GPT3.into()];
+//! let counter = Counter::new(options);
+//! let counts = counter.count_str("Hello, world!\n").unwrap();
+//! assert_eq!(counts.lines, 1);
+//! assert_eq!(counts.words, 2);
+//! assert_eq!(counts.chars, 14);
+//! ```
+
+#![warn(missing_docs)]
+
+rust_i18n::i18n!("locales", fallback = "en");
+
+mod cjk;
+mod cmd;
+mod counter;
+mod counts;
+mod input_processor;
+mod locales;
+mod output;
+mod sentencepiece;
+mod tokenizer;
+mod walk;
+
+#[doc(hidden)]
+pub use cmd::run;
+pub use counter::{CountError, Counter};
+pub use counts::{CountOptions, InputCounts, WordMode};
+pub use sentencepiece::SentencePieceModel;
+pub use tokenizer::{LoadError, TokenizeError, Tokenizer, TokenizerModel};
@@ -24,6 +24,7 @@ mod tests {
             "tc: test.txt: ファイルの読み込み中にエラーが発生しました"
         );
         assert_eq!(t!("total"), "合計");
+        assert_eq!(t!("header_tokens", model = "gpt4o"), "トークン数(gpt4o)");
         assert_eq!(
             t!(
                 "error_opening_file",
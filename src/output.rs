@@ -0,0 +1,559 @@
+use crate::counts::{CountOptions, InputCounts};
+use crate::input_processor::{ProcessOptions, TotalMode};
+use clap::ValueEnum;
+use rust_i18n::t;
+use serde_json::{json, Map, Value};
+use std::io::{self, Write};
+use unicode_width::UnicodeWidthStr;
+
+/// Version of the JSON document written by `--format json`, raised whenever
+/// a field changes meaning or is removed.
+pub(crate) const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// The formats counts can be written in.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Right-aligned columns followed by the file name, like `wc`. Columns
+    /// are as wide as the largest count, so rows are written once every
+    /// input is counted.
+    #[default]
+    Text,
+    /// A single JSON document written once every input is counted.
+    Json,
+    /// Comma-separated values with a header row.
+    Csv,
+    /// Tab-separated values with a header row.
+    Tsv,
+    /// One JSON object per line, written as soon as each input is counted.
+    Ndjson,
+    /// A GitHub-flavoured Markdown table with localized column names.
+    Markdown,
+}
+
+impl OutputFormat {
+    /// Returns whether inputs are written in the order they finish counting
+    /// rather than in command line order.
+    pub(crate) fn is_streamed(self) -> bool {
+        self == OutputFormat::Ndjson
+    }
+}
+
+/// A column of the output, in the order columns are printed.
+#[derive(Debug, Clone, Copy)]
+enum Column {
+    Lines,
+    Words,
+    Sentences,
+    Paragraphs,
+    Chars,
+    Graphemes,
+    Bytes,
+    MaxLineLength,
+    /// The token count of the tokenizer at this index.
+    Tokens(usize),
+}
+
+impl Column {
+    /// Returns the columns enabled by `options`.
+    fn enabled(options: &CountOptions) -> Vec<Column> {
+        let flags = [
+            (options.show_lines, Column::Lines),
+            (options.show_words, Column::Words),
+            (options.show_sentences, Column::Sentences),
+            (options.show_paragraphs, Column::Paragraphs),
+            (options.show_chars, Column::Chars),
+            (options.show_graphemes, Column::Graphemes),
+            (options.show_bytes, Column::Bytes),
+            (options.show_max_line_length, Column::MaxLineLength),
+        ];
+        let mut columns: Vec<Column> = flags
+            .into_iter()
+            .filter(|&(enabled, _)| enabled)
+            .map(|(_, column)| column)
+            .collect();
+        if options.show_tokens {
+            columns.extend((0..options.tokenizers.len()).map(Column::Tokens));
+        }
+        columns
+    }
+
+    /// Returns the name of this column in machine-readable output.
+    ///
+    /// All token columns share one name, see [`Output::column_name`].
+    fn key(self) -> &'static str {
+        match self {
+            Column::Lines => "lines",
+            Column::Words => "words",
+            Column::Sentences => "sentences",
+            Column::Paragraphs => "paragraphs",
+            Column::Chars => "chars",
+            Column::Graphemes => "graphemes",
+            Column::Bytes => "bytes",
+            Column::MaxLineLength => "max_line_length",
+            Column::Tokens(_) => "tokens",
+        }
+    }
+
+    fn value(self, counts: &InputCounts) -> usize {
+        match self {
+            Column::Lines => counts.lines,
+            Column::Words => counts.words,
+            Column::Sentences => counts.sentences,
+            Column::Paragraphs => counts.paragraphs,
+            Column::Chars => counts.chars,
+            Column::Graphemes => counts.graphemes,
+            Column::Bytes => counts.bytes,
+            Column::MaxLineLength => counts.max_line_length,
+            Column::Tokens(index) => counts.tokens.get(index).copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// Writes the counts of each input, the errors and the total in an
+/// [`OutputFormat`].
+///
+/// Formats that cannot be written piece by piece are held until
+/// [`finish`](Self::finish).
+pub(crate) struct Output<'a, W: Write> {
+    writer: &'a mut W,
+    format: OutputFormat,
+    columns: Vec<Column>,
+    /// Names of the tokenizers, in the order of their token columns.
+    tokenizer_names: &'a [String],
+    inputs: Vec<Value>,
+    total: Option<Value>,
+    /// Rows of text output, as the counts of each column and a name.
+    rows: Vec<(Vec<usize>, String)>,
+    /// Whether text output starts with a row of column names.
+    text_header: bool,
+    /// Whether the total is the only row, which text output leaves unnamed
+    /// like `wc --total=only`.
+    total_only: bool,
+}
+
+impl<'a, W: Write> Output<'a, W> {
+    pub(crate) fn new(
+        writer: &'a mut W,
+        options: &CountOptions,
+        process_options: &'a ProcessOptions,
+    ) -> Self {
+        Self {
+            writer,
+            format: process_options.format,
+            columns: Column::enabled(options),
+            tokenizer_names: &process_options.tokenizer_names,
+            inputs: Vec::new(),
+            total: None,
+            rows: Vec::new(),
+            text_header: process_options.header,
+            total_only: process_options.total == TotalMode::Only,
+        }
+    }
+
+    /// Writes what comes before the first input, if anything.
+    pub(crate) fn header(&mut self) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Text | OutputFormat::Json | OutputFormat::Ndjson => Ok(()),
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                let mut fields: Vec<String> = self
+                    .columns
+                    .iter()
+                    .map(|&column| self.column_name(column))
+                    .collect();
+                fields.push("path".to_string());
+                self.delimited_row(&fields)
+            }
+            OutputFormat::Markdown => {
+                let mut labels: Vec<String> = self
+                    .columns
+                    .iter()
+                    .map(|&column| escape_markdown(&self.column_label(column)))
+                    .collect();
+                labels.push(escape_markdown(&t!("header_file")));
+                let mut alignments = vec!["---:"; self.columns.len()];
+                alignments.push(":---");
+                writeln!(self.writer, "| {} |", labels.join(" | "))?;
+                writeln!(self.writer, "| {} |", alignments.join(" | "))
+            }
+        }
+    }
+
+    /// Writes the counts of an input, named `name` unless it is stdin.
+    pub(crate) fn counts(&mut self, name: Option<&str>, counts: &InputCounts) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Text => {
+                self.text_row(name.unwrap_or_default(), counts);
+                Ok(())
+            }
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                self.delimited_counts(name.unwrap_or_default(), counts)
+            }
+            OutputFormat::Markdown => {
+                self.markdown_row(&escape_markdown(name.unwrap_or_default()), counts, "")
+            }
+            OutputFormat::Json => {
+                let mut input = Map::new();
+                input.insert("path".to_string(), json!(name));
+                input.extend(self.json_counts(counts));
+                self.inputs.push(Value::Object(input));
+                Ok(())
+            }
+            OutputFormat::Ndjson => {
+                let mut record = Map::new();
+                record.insert("type".to_string(), json!("input"));
+                record.insert("path".to_string(), json!(name));
+                record.extend(self.json_counts(counts));
+                self.json_line(&Value::Object(record))
+            }
+        }
+    }
+
+    /// Records that an input could not be counted.
+    ///
+    /// Errors are reported on stderr by the caller, so text output leaves
+    /// them out.
+    pub(crate) fn error(&mut self, name: Option<&str>, error: &str) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Text | OutputFormat::Csv | OutputFormat::Tsv | OutputFormat::Markdown => {
+                Ok(())
+            }
+            OutputFormat::Json => {
+                self.inputs.push(json!({ "path": name, "error": error }));
+                Ok(())
+            }
+            OutputFormat::Ndjson => {
+                self.json_line(&json!({ "type": "error", "path": name, "error": error }))
+            }
+        }
+    }
+
+    /// Writes the sum of the counts of all inputs.
+    pub(crate) fn total(&mut self, counts: &InputCounts) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Text => {
+                let name = if self.total_only { "" } else { &t!("total") };
+                self.text_row(name, counts);
+                Ok(())
+            }
+            OutputFormat::Csv | OutputFormat::Tsv => self.delimited_counts("total", counts),
+            OutputFormat::Markdown => {
+                self.markdown_row(&escape_markdown(&t!("total")), counts, "**")
+            }
+            OutputFormat::Json => {
+                self.total = Some(Value::Object(self.json_counts(counts)));
+                Ok(())
+            }
+            OutputFormat::Ndjson => {
+                let mut record = Map::new();
+                record.insert("type".to_string(), json!("total"));
+                record.extend(self.json_counts(counts));
+                self.json_line(&Value::Object(record))
+            }
+        }
+    }
+
+    /// Writes whatever was held back until every input was counted.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Text => {
+                let width = self
+                    .rows
+                    .iter()
+                    .flat_map(|(values, _)| values)
+                    .map(|value| value.to_string().len())
+                    .max()
+                    .unwrap_or_default();
+                let mut widths = vec![width; self.columns.len()];
+                if self.text_header {
+                    let labels: Vec<String> = self
+                        .columns
+                        .iter()
+                        .map(|&column| self.text_label(column))
+                        .collect();
+                    let mut output = String::new();
+                    for (label, width) in labels.iter().zip(&mut widths) {
+                        *width = (*width).max(label.width());
+                        output.push_str(&" ".repeat(*width - label.width()));
+                        output.push_str(label);
+                        output.push(' ');
+                    }
+                    output.push_str(&t!("header_file").to_uppercase());
+                    writeln!(self.writer, "{}", output)?;
+                }
+                for (values, name) in &self.rows {
+                    let mut output = String::new();
+                    for (value, width) in values.iter().zip(&widths) {
+                        output.push_str(&format!("{: >width$} ", value));
+                    }
+                    output.push_str(name);
+                    writeln!(self.writer, "{}", output.trim_end())?;
+                }
+                Ok(())
+            }
+            OutputFormat::Csv
+            | OutputFormat::Tsv
+            | OutputFormat::Ndjson
+            | OutputFormat::Markdown => Ok(()),
+            OutputFormat::Json => {
+                let document = json!({
+                    "schema_version": JSON_SCHEMA_VERSION,
+                    "inputs": self.inputs,
+                    "total": self.total,
+                });
+                serde_json::to_writer_pretty(&mut *self.writer, &document)?;
+                writeln!(self.writer)
+            }
+        }
+    }
+
+    fn text_row(&mut self, name: &str, counts: &InputCounts) {
+        let values = self.columns.iter().map(|column| column.value(counts));
+        self.rows.push((values.collect(), name.to_string()));
+    }
+
+    /// Returns the name of `column` in header rows. Token columns are named
+    /// after their tokenizer when there are several.
+    fn column_name(&self, column: Column) -> String {
+        match column {
+            Column::Tokens(index) if self.tokenizer_names.len() > 1 => {
+                format!(
+                    "tokens({})",
+                    self.tokenizer_names.get(index).map_or("", String::as_str)
+                )
+            }
+            column => column.key().to_string(),
+        }
+    }
+
+    /// Returns the localized name of `column` in table headers.
+    fn column_label(&self, column: Column) -> String {
+        let key = match column {
+            Column::Tokens(index) => {
+                let model = self.tokenizer_names.get(index).map_or("", String::as_str);
+                return t!("header_tokens", model = model).to_string();
+            }
+            Column::Lines => "header_lines",
+            Column::Words => "header_words",
+            Column::Sentences => "header_sentences",
+            Column::Paragraphs => "header_paragraphs",
+            Column::Chars => "header_chars",
+            Column::Graphemes => "header_graphemes",
+            Column::Bytes => "header_bytes",
+            Column::MaxLineLength => "header_max_line_length",
+        };
+        t!(key).to_string()
+    }
+
+    /// Returns the upper case name of `column` in text headers, a single
+    /// word so that the header splits on whitespace like the rows below it.
+    fn text_label(&self, column: Column) -> String {
+        match column {
+            Column::Tokens(index) => {
+                // The model is filled in after upper casing so that it keeps
+                // the case it is selected with.
+                let model = self.tokenizer_names.get(index).map_or("", String::as_str);
+                t!("header_tokens", model = "\u{0}")
+                    .to_uppercase()
+                    .replace('\u{0}', model)
+            }
+            Column::MaxLineLength => t!("header_max_line").to_uppercase(),
+            _ => self.column_label(column).to_uppercase(),
+        }
+    }
+
+    /// Writes a Markdown table row, wrapping each cell in `emphasis`.
+    fn markdown_row(&mut self, name: &str, counts: &InputCounts, emphasis: &str) -> io::Result<()> {
+        let mut cells: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| format!("{emphasis}{}{emphasis}", column.value(counts)))
+            .collect();
+        cells.push(format!("{emphasis}{name}{emphasis}"));
+        writeln!(self.writer, "| {} |", cells.join(" | "))
+    }
+
+    fn delimited_counts(&mut self, name: &str, counts: &InputCounts) -> io::Result<()> {
+        let mut fields: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| column.value(counts).to_string())
+            .collect();
+        fields.push(name.to_string());
+        self.delimited_row(&fields)
+    }
+
+    /// Writes a CSV or TSV row, quoting fields as RFC 4180 does.
+    fn delimited_row(&mut self, fields: &[String]) -> io::Result<()> {
+        let delimiter = if self.format == OutputFormat::Tsv {
+            '\t'
+        } else {
+            ','
+        };
+        let row: Vec<String> = fields
+            .iter()
+            .map(|field| {
+                if field.contains([delimiter, '"', '\n', '\r']) {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field.clone()
+                }
+            })
+            .collect();
+        writeln!(self.writer, "{}", row.join(&delimiter.to_string()))
+    }
+
+    /// Writes `value` as a single line of JSON.
+    fn json_line(&mut self, value: &Value) -> io::Result<()> {
+        serde_json::to_writer(&mut *self.writer, value)?;
+        writeln!(self.writer)
+    }
+
+    /// Returns the enabled counts as JSON fields, with the token counts as
+    /// an array of `{"model", "count"}` objects.
+    fn json_counts(&self, counts: &InputCounts) -> Map<String, Value> {
+        let mut fields = Map::new();
+        let mut tokens = Vec::new();
+        for &column in &self.columns {
+            let value = column.value(counts);
+            match column {
+                Column::Tokens(index) => {
+                    let model = self.tokenizer_names.get(index);
+                    tokens.push(json!({ "model": model, "count": value }));
+                }
+                column => {
+                    fields.insert(column.key().to_string(), json!(value));
+                }
+            }
+        }
+        if !tokens.is_empty() {
+            fields.insert("tokens".to_string(), Value::Array(tokens));
+        }
+        fields
+    }
+}
+
+/// Escapes the characters of `text` that Markdown would otherwise read as
+/// table or inline syntax.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '|' | '*' | '_' | '`' | '[' | ']' | '<' | '>' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words_only() -> CountOptions {
+        CountOptions {
+            show_words: true,
+            ..CountOptions::default()
+        }
+    }
+
+    fn written(format: OutputFormat, name: &str) -> String {
+        let mut buffer = Vec::new();
+        let counts = InputCounts {
+            words: 3,
+            ..InputCounts::default()
+        };
+        let process_options = ProcessOptions {
+            format,
+            ..ProcessOptions::default()
+        };
+        let mut output = Output::new(&mut buffer, &words_only(), &process_options);
+        output.header().unwrap();
+        output.counts(Some(name), &counts).unwrap();
+        output.finish().unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_csv_quoting() {
+        assert_eq!(
+            written(OutputFormat::Csv, "plain.txt"),
+            "words,path\n3,plain.txt\n"
+        );
+        assert_eq!(
+            written(OutputFormat::Csv, "a,\"b\"\nc.txt"),
+            "words,path\n3,\"a,\"\"b\"\"\nc.txt\"\n"
+        );
+        assert_eq!(
+            written(OutputFormat::Csv, "tab\there"),
+            "words,path\n3,tab\there\n"
+        );
+    }
+
+    #[test]
+    fn test_text_column_width() {
+        let mut buffer = Vec::new();
+        let options = CountOptions {
+            show_lines: true,
+            ..words_only()
+        };
+        let process_options = ProcessOptions::default();
+        let mut output = Output::new(&mut buffer, &options, &process_options);
+        let small = InputCounts {
+            lines: 1,
+            words: 7,
+            ..InputCounts::default()
+        };
+        let large = InputCounts {
+            lines: 2,
+            words: 123_456_789,
+            ..InputCounts::default()
+        };
+        output.counts(Some("small.txt"), &small).unwrap();
+        output.counts(None, &large).unwrap();
+        output.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "        1         7 small.txt\n        2 123456789\n"
+        );
+
+        let mut buffer = Vec::new();
+        let mut output = Output::new(&mut buffer, &options, &process_options);
+        output.counts(Some("a.txt"), &small).unwrap();
+        output.counts(Some("b.txt"), &small).unwrap();
+        output
+            .total(&InputCounts {
+                lines: 2,
+                words: 14,
+                ..InputCounts::default()
+            })
+            .unwrap();
+        output.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            " 1  7 a.txt\n 1  7 b.txt\n 2 14 total\n"
+        );
+    }
+
+    #[test]
+    fn test_escape_markdown() {
+        assert_eq!(escape_markdown("notes.md"), "notes.md");
+        assert_eq!(escape_markdown("a|b_c*.txt"), "a\\|b\\_c\\*.txt");
+        assert_eq!(escape_markdown("line\nbreak"), "line break");
+    }
+
+    #[test]
+    fn test_tsv_quoting() {
+        assert_eq!(
+            written(OutputFormat::Tsv, "a,b.txt"),
+            "words\tpath\n3\ta,b.txt\n"
+        );
+        assert_eq!(
+            written(OutputFormat::Tsv, "tab\there"),
+            "words\tpath\n3\t\"tab\there\"\n"
+        );
+    }
+}
@@ -0,0 +1,677 @@
+//! A minimal reader and encoder for SentencePiece `.model` files.
+//!
+//! Only what is needed to count pieces is implemented: the vocabulary, the
+//! model type and the normalizer, whose precompiled rules are applied the
+//! way HuggingFace `tokenizers` does.
+
+use crate::tokenizer::LoadError;
+use spm_precompiled::Precompiled;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::Range;
+
+/// The character SentencePiece replaces spaces with.
+const SPACE_SYMBOL: char = '\u{2581}';
+/// The penalty applied to unknown characters by the unigram model.
+const UNK_PENALTY: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModelType {
+    Unigram,
+    Bpe,
+    Word,
+    Char,
+}
+
+/// A SentencePiece model loaded from a serialized `ModelProto`.
+#[derive(Debug)]
+pub struct SentencePieceModel {
+    model_type: ModelType,
+    byte_fallback: bool,
+    /// Whether pieces never span spaces, so that words can be segmented
+    /// one at a time.
+    split_by_whitespace: bool,
+    /// Whether spaces end pieces rather than start them.
+    whitespace_as_suffix: bool,
+    /// The precompiled normalization rules, such as NFKC, if the model has
+    /// any.
+    charsmap: Option<Precompiled>,
+    add_dummy_prefix: bool,
+    remove_extra_whitespaces: bool,
+    escape_whitespaces: bool,
+    /// Scores of the pieces text can be segmented into.
+    scores: HashMap<String, f32>,
+    /// User-defined pieces, which are never split.
+    user_defined: Vec<String>,
+    max_piece_chars: usize,
+    min_score: f32,
+    max_score: f32,
+}
+
+impl SentencePieceModel {
+    /// Parses a serialized SentencePiece `ModelProto`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        let mut model = SentencePieceModel {
+            model_type: ModelType::Unigram,
+            byte_fallback: false,
+            split_by_whitespace: true,
+            whitespace_as_suffix: false,
+            charsmap: None,
+            add_dummy_prefix: true,
+            remove_extra_whitespaces: true,
+            escape_whitespaces: true,
+            scores: HashMap::new(),
+            user_defined: Vec::new(),
+            max_piece_chars: 1,
+            min_score: f32::MAX,
+            max_score: f32::MIN,
+        };
+
+        for field in Fields::new(bytes) {
+            match field? {
+                (1, Value::Bytes(piece)) => model.add_piece(piece)?,
+                (2, Value::Bytes(trainer_spec)) => {
+                    for field in Fields::new(trainer_spec) {
+                        match field? {
+                            (3, Value::Varint(model_type)) => {
+                                model.model_type = match model_type {
+                                    1 => ModelType::Unigram,
+                                    2 => ModelType::Bpe,
+                                    3 => ModelType::Word,
+                                    4 => ModelType::Char,
+                                    _ => return Err("unknown SentencePiece model type".into()),
+                                }
+                            }
+                            (22, Value::Varint(value)) => model.split_by_whitespace = value != 0,
+                            (24, Value::Varint(value)) => model.whitespace_as_suffix = value != 0,
+                            (35, Value::Varint(byte_fallback)) => {
+                                model.byte_fallback = byte_fallback != 0
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                (3, Value::Bytes(normalizer_spec)) => {
+                    for field in Fields::new(normalizer_spec) {
+                        match field? {
+                            (2, Value::Bytes(charsmap)) if !charsmap.is_empty() => {
+                                model.charsmap = Some(Precompiled::from(charsmap)?)
+                            }
+                            (3, Value::Varint(value)) => model.add_dummy_prefix = value != 0,
+                            (4, Value::Varint(value)) => {
+                                model.remove_extra_whitespaces = value != 0
+                            }
+                            (5, Value::Varint(value)) => model.escape_whitespaces = value != 0,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if model.scores.is_empty() {
+            return Err("SentencePiece model has no pieces".into());
+        }
+        if model.model_type == ModelType::Word && model.whitespace_as_suffix {
+            return Err(
+                "SentencePiece word models with whitespace suffixes are not supported".into(),
+            );
+        }
+        // User-defined pieces always win, as in SentencePiece.
+        for piece in &model.user_defined {
+            let score = piece.chars().count() as f32 * model.max_score - 0.1;
+            model.scores.insert(piece.clone(), score);
+        }
+        Ok(model)
+    }
+
+    fn add_piece(&mut self, bytes: &[u8]) -> Result<(), LoadError> {
+        let mut piece = String::new();
+        let mut score = 0.0;
+        let mut piece_type = 1;
+        for field in Fields::new(bytes) {
+            match field? {
+                (1, Value::Bytes(text)) => piece = String::from_utf8(text.to_vec())?,
+                (2, Value::Fixed32(bits)) => score = f32::from_bits(bits),
+                (3, Value::Varint(value)) => piece_type = value,
+                _ => {}
+            }
+        }
+
+        match piece_type {
+            // NORMAL
+            1 => {
+                self.min_score = self.min_score.min(score);
+                self.max_score = self.max_score.max(score);
+            }
+            // USER_DEFINED
+            4 => self.user_defined.push(piece.clone()),
+            // UNKNOWN, CONTROL, UNUSED and BYTE pieces never match text.
+            _ => return Ok(()),
+        }
+        self.max_piece_chars = self.max_piece_chars.max(piece.chars().count());
+        self.scores.insert(piece, score);
+        Ok(())
+    }
+
+    /// Counts the pieces `text` is encoded into.
+    pub fn count_pieces(&self, text: &str) -> usize {
+        let normalized = self.normalize(text);
+        let space = if self.escape_whitespaces {
+            SPACE_SYMBOL
+        } else {
+            ' '
+        };
+        match self.model_type {
+            ModelType::Word => split_words(&normalized, space).count(),
+            ModelType::Char => normalized.chars().count(),
+            // Pieces may then hold spaces anywhere, so the text is segmented
+            // as a whole.
+            _ if self.whitespace_as_suffix || !self.split_by_whitespace => {
+                self.count_segmented(&normalized)
+            }
+            _ => split_words(&normalized, space)
+                .map(|word| self.count_segmented(word))
+                .sum(),
+        }
+    }
+
+    /// Counts the pieces of `text` with the unigram or BPE algorithm.
+    fn count_segmented(&self, text: &str) -> usize {
+        if self.model_type == ModelType::Bpe {
+            self.count_bpe(text)
+        } else {
+            self.count_unigram(text)
+        }
+    }
+
+    /// Applies the precompiled rules, then the whitespace handling of the
+    /// normalizer. Like in SentencePiece, only the spaces left after the
+    /// rules are collapsed, so other whitespace is kept unless the rules
+    /// turn it into spaces.
+    fn normalize(&self, text: &str) -> String {
+        let text = match &self.charsmap {
+            Some(charsmap) => Cow::Owned(charsmap.normalize_string(text)),
+            None => Cow::Borrowed(text),
+        };
+        let mut normalized = String::with_capacity(text.len() + SPACE_SYMBOL.len_utf8());
+        if self.remove_extra_whitespaces {
+            for word in text.split(' ').filter(|w| !w.is_empty()) {
+                if !normalized.is_empty() {
+                    normalized.push(' ');
+                }
+                normalized.push_str(word);
+            }
+        } else {
+            normalized.push_str(&text);
+        }
+        if self.add_dummy_prefix && !normalized.is_empty() {
+            if self.whitespace_as_suffix {
+                normalized.push(' ');
+            } else {
+                normalized.insert(0, ' ');
+            }
+        }
+        if self.escape_whitespaces {
+            normalized = normalized.replace(' ', "\u{2581}");
+        }
+        normalized
+    }
+
+    /// Number of pieces an unknown character is encoded into.
+    fn unknown_pieces(&self, unknown: &str) -> usize {
+        if self.byte_fallback {
+            unknown.len()
+        } else {
+            1
+        }
+    }
+
+    /// Finds the segmentation with the best total score with the Viterbi
+    /// algorithm, like the unigram model of SentencePiece does.
+    fn count_unigram(&self, word: &str) -> usize {
+        let boundaries: Vec<usize> = word
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([word.len()])
+            .collect();
+        let chars = boundaries.len() - 1;
+        let unk_score = self.min_score - UNK_PENALTY;
+
+        // best[i] holds the best score of word[..boundaries[i]] and the
+        // character index its last piece starts at.
+        let mut best: Vec<(f32, usize)> = vec![(f32::NEG_INFINITY, 0); chars + 1];
+        best[0].0 = 0.0;
+        for start in 0..chars {
+            let base = best[start].0;
+            if base == f32::NEG_INFINITY {
+                continue;
+            }
+            let mut has_single_char = false;
+            for end in start + 1..=chars.min(start + self.max_piece_chars) {
+                let piece = &word[boundaries[start]..boundaries[end]];
+                let Some(&score) = self.scores.get(piece) else {
+                    continue;
+                };
+                has_single_char |= end == start + 1;
+                if base + score > best[end].0 {
+                    best[end] = (base + score, start);
+                }
+            }
+            if !has_single_char && base + unk_score > best[start + 1].0 {
+                best[start + 1] = (base + unk_score, start);
+            }
+        }
+
+        let mut count = 0;
+        let mut previous_unknown = false;
+        let mut end = chars;
+        while end > 0 {
+            let start = best[end].1;
+            let piece = &word[boundaries[start]..boundaries[end]];
+            if self.scores.contains_key(piece) {
+                count += 1;
+                previous_unknown = false;
+            } else if self.byte_fallback {
+                count += piece.len();
+            } else if !previous_unknown {
+                // Consecutive unknown characters become a single piece.
+                count += 1;
+                previous_unknown = true;
+            }
+            end = start;
+        }
+        count
+    }
+
+    /// Repeatedly merges the adjacent pair of symbols with the best score,
+    /// like the BPE model of SentencePiece does.
+    fn count_bpe(&self, word: &str) -> usize {
+        let mut symbols: Vec<Symbol> = Vec::new();
+        let mut rest = 0;
+        while rest < word.len() {
+            let len = self
+                .user_defined
+                .iter()
+                .filter(|piece| word[rest..].starts_with(piece.as_str()))
+                .map(|piece| piece.len())
+                .max()
+                .unwrap_or_else(|| word[rest..].chars().next().unwrap().len_utf8());
+            let index = symbols.len();
+            symbols.push(Symbol {
+                range: rest..rest + len,
+                prev: index.checked_sub(1),
+                next: Some(index + 1),
+            });
+            rest += len;
+        }
+        if let Some(last) = symbols.last_mut() {
+            last.next = None;
+        }
+
+        let mut candidates = BinaryHeap::new();
+        for left in 1..symbols.len() {
+            self.push_merge(&mut candidates, word, &symbols, left - 1, left);
+        }
+        while let Some(merge) = candidates.pop() {
+            let (left, right) = (merge.left, merge.right);
+            let valid = symbols[left].next == Some(right)
+                && symbols[left].range.start == merge.range.start
+                && symbols[right].range.end == merge.range.end;
+            if !valid {
+                continue;
+            }
+            symbols[left].range.end = merge.range.end;
+            symbols[left].next = symbols[right].next;
+            symbols[right].range = 0..0;
+            symbols[right].prev = None;
+            symbols[right].next = None;
+            if let Some(next) = symbols[left].next {
+                symbols[next].prev = Some(left);
+                self.push_merge(&mut candidates, word, &symbols, left, next);
+            }
+            if let Some(prev) = symbols[left].prev {
+                self.push_merge(&mut candidates, word, &symbols, prev, left);
+            }
+        }
+
+        symbols
+            .iter()
+            .filter(|symbol| !symbol.range.is_empty())
+            .map(|symbol| {
+                let piece = &word[symbol.range.clone()];
+                if self.scores.contains_key(piece) {
+                    1
+                } else {
+                    self.unknown_pieces(piece)
+                }
+            })
+            .sum()
+    }
+
+    fn push_merge(
+        &self,
+        candidates: &mut BinaryHeap<Merge>,
+        word: &str,
+        symbols: &[Symbol],
+        left: usize,
+        right: usize,
+    ) {
+        let range = symbols[left].range.start..symbols[right].range.end;
+        if let Some(&score) = self.scores.get(&word[range.clone()]) {
+            candidates.push(Merge {
+                score,
+                range,
+                left,
+                right,
+            });
+        }
+    }
+}
+
+/// Splits normalized text into words that start with a run of spaces.
+///
+/// Unless a model was trained with `split_by_whitespace` off or with
+/// `treat_whitespace_as_suffix`, its pieces only ever have spaces at their
+/// start, so no piece spans two words.
+fn split_words(text: &str, space: char) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let body = rest.trim_start_matches(space);
+        let offset = rest.len() - body.len();
+        let end = body.find(space).map_or(rest.len(), |end| offset + end);
+        let (word, tail) = rest.split_at(end);
+        rest = tail;
+        Some(word)
+    })
+}
+
+struct Symbol {
+    range: Range<usize>,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// A candidate merge of two adjacent symbols.
+struct Merge {
+    score: f32,
+    range: Range<usize>,
+    left: usize,
+    right: usize,
+}
+
+impl Ord for Merge {
+    /// Orders merges by score, preferring the leftmost on ties.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.range.start.cmp(&self.range.start))
+    }
+}
+
+impl PartialOrd for Merge {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Merge {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Merge {}
+
+/// A field value in the protobuf wire format.
+enum Value<'a> {
+    Varint(u64),
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+/// Iterates over the fields of a serialized protobuf message.
+struct Fields<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn read_varint(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.bytes.split_first()?;
+            self.bytes = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(bytes)
+    }
+
+    fn read_field(&mut self) -> Option<(u64, Value<'a>)> {
+        let key = self.read_varint()?;
+        let value = match key & 0x7 {
+            0 => Value::Varint(self.read_varint()?),
+            1 => {
+                self.read_bytes(8)?;
+                Value::Fixed64
+            }
+            2 => {
+                let len = usize::try_from(self.read_varint()?).ok()?;
+                Value::Bytes(self.read_bytes(len)?)
+            }
+            5 => Value::Fixed32(u32::from_le_bytes(self.read_bytes(4)?.try_into().ok()?)),
+            _ => return None,
+        };
+        Some((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u64, Value<'a>), LoadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        match self.read_field() {
+            Some(field) => Some(Ok(field)),
+            None => {
+                self.bytes = &[];
+                Some(Err("not a valid SentencePiece model".into()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn message(field: u64, bytes: &[u8], out: &mut Vec<u8>) {
+        varint(field << 3 | 2, out);
+        varint(bytes.len() as u64, out);
+        out.extend_from_slice(bytes);
+    }
+
+    fn flag(field: u64, value: u64, out: &mut Vec<u8>) {
+        varint(field << 3, out);
+        varint(value, out);
+    }
+
+    /// Serializes a model with the given pieces, scores and piece types.
+    fn model_bytes(
+        pieces: &[(&str, f32, u64)],
+        trainer_spec: &[u8],
+        normalizer_spec: &[u8],
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        for &(piece, score, piece_type) in pieces {
+            let mut bytes = Vec::new();
+            message(1, piece.as_bytes(), &mut bytes);
+            varint(2 << 3 | 5, &mut bytes);
+            bytes.extend_from_slice(&score.to_le_bytes());
+            flag(3, piece_type, &mut bytes);
+            message(1, &bytes, &mut out);
+        }
+        message(2, trainer_spec, &mut out);
+        message(3, normalizer_spec, &mut out);
+        out
+    }
+
+    const PIECES: [(&str, f32, u64); 10] = [
+        ("<unk>", 0.0, 2),
+        ("<s>", 0.0, 3),
+        ("\u{2581}", -1.0, 1),
+        ("\u{2581}hello", -2.0, 1),
+        ("\u{2581}he", -3.0, 1),
+        ("llo", -3.0, 1),
+        ("h", -4.0, 1),
+        ("e", -4.0, 1),
+        ("l", -4.0, 1),
+        ("o", -4.0, 1),
+    ];
+
+    #[test]
+    fn test_unigram() {
+        let model = SentencePieceModel::from_bytes(&model_bytes(&PIECES, &[], &[])).unwrap();
+        assert_eq!(model.model_type, ModelType::Unigram);
+        // "▁hello" beats "▁he" + "llo".
+        assert_eq!(model.count_pieces("hello"), 1);
+        assert_eq!(model.count_pieces("  hello   hello "), 2);
+        // "▁he", "l", "l" and a single piece for the unknown "xy".
+        assert_eq!(model.count_pieces("hellxy"), 4);
+        assert_eq!(model.count_pieces(""), 0);
+    }
+
+    #[test]
+    fn test_only_spaces_are_collapsed() {
+        let model = SentencePieceModel::from_bytes(&model_bytes(&PIECES, &[], &[])).unwrap();
+        // Without normalization rules, the newline is an unknown character
+        // and no space comes before the second "hello": "▁hello", "\n",
+        // "h", "e", "llo".
+        assert_eq!(model.normalize("hello\nhello "), "▁hello\nhello");
+        assert_eq!(model.count_pieces("hello\nhello "), 5);
+    }
+
+    #[test]
+    fn test_unigram_byte_fallback() {
+        let mut trainer_spec = Vec::new();
+        flag(35, 1, &mut trainer_spec);
+        let mut normalizer_spec = Vec::new();
+        flag(4, 0, &mut normalizer_spec);
+        let model =
+            SentencePieceModel::from_bytes(&model_bytes(&PIECES, &trainer_spec, &normalizer_spec))
+                .unwrap();
+        // "▁hello", then the bytes of "\n" and "é".
+        assert_eq!(model.count_pieces("hello\né"), 4);
+        // Extra spaces are kept: "▁", "▁hello".
+        assert_eq!(model.count_pieces(" hello"), 2);
+    }
+
+    #[test]
+    fn test_bpe() {
+        let mut trainer_spec = Vec::new();
+        flag(3, 2, &mut trainer_spec);
+        let pieces = [
+            ("<unk>", 0.0, 2),
+            ("\u{2581}", -1.0, 1),
+            ("h", -1.0, 1),
+            ("e", -1.0, 1),
+            ("l", -1.0, 1),
+            ("o", -1.0, 1),
+            ("ll", -2.0, 1),
+            ("he", -3.0, 1),
+            ("llo", -4.0, 1),
+            ("\u{2581}he", -5.0, 1),
+            ("hello", -6.0, 1),
+            ("<sep>", 0.0, 4),
+        ];
+        let model =
+            SentencePieceModel::from_bytes(&model_bytes(&pieces, &trainer_spec, &[])).unwrap();
+        assert_eq!(model.model_type, ModelType::Bpe);
+        // "ll", then "he", then "llo", then "▁he" wins over "hello".
+        assert_eq!(model.count_pieces("hello"), 2);
+        // The user-defined "<sep>" is never split, "x" is unknown.
+        assert_eq!(model.count_pieces("hello<sep>x"), 4);
+    }
+
+    #[test]
+    fn test_pieces_across_spaces() {
+        let pieces = [
+            ("<unk>", 0.0, 2),
+            ("\u{2581}", -1.0, 1),
+            ("hello\u{2581}", -2.0, 1),
+            ("\u{2581}hello\u{2581}hello", -2.0, 1),
+            ("h", -4.0, 1),
+            ("e", -4.0, 1),
+            ("l", -4.0, 1),
+            ("o", -4.0, 1),
+        ];
+        let mut trainer_spec = Vec::new();
+        flag(24, 1, &mut trainer_spec);
+        let model =
+            SentencePieceModel::from_bytes(&model_bytes(&pieces, &trainer_spec, &[])).unwrap();
+        // The dummy space comes last: "hello▁", "hello▁".
+        assert_eq!(
+            model.normalize(" hello  hello"),
+            "hello\u{2581}hello\u{2581}"
+        );
+        assert_eq!(model.count_pieces(" hello  hello"), 2);
+
+        let mut trainer_spec = Vec::new();
+        flag(22, 0, &mut trainer_spec);
+        let model =
+            SentencePieceModel::from_bytes(&model_bytes(&pieces, &trainer_spec, &[])).unwrap();
+        // "▁hello▁hello" spans both words.
+        assert_eq!(model.count_pieces("hello hello"), 1);
+
+        let mut trainer_spec = Vec::new();
+        flag(3, 3, &mut trainer_spec);
+        flag(24, 1, &mut trainer_spec);
+        assert!(SentencePieceModel::from_bytes(&model_bytes(&pieces, &trainer_spec, &[])).is_err());
+    }
+
+    #[test]
+    fn test_split_words() {
+        let words: Vec<&str> = split_words("▁a▁▁bc▁", SPACE_SYMBOL).collect();
+        assert_eq!(words, ["▁a", "▁▁bc", "▁"]);
+        let words: Vec<&str> = split_words("ab c", ' ').collect();
+        assert_eq!(words, ["ab", " c"]);
+    }
+
+    #[test]
+    fn test_invalid_model() {
+        assert!(SentencePieceModel::from_bytes(b"").is_err());
+        assert!(SentencePieceModel::from_bytes(b"\x0a\x05ab").is_err());
+        assert!(SentencePieceModel::from_bytes(b"{\"model\": {}}").is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use crate::sentencepiece::SentencePieceModel;
+use base64::engine::general_purpose;
+use base64::Engine as _;
+use clap::ValueEnum;
+use rustc_hash::FxHashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use tiktoken_rs::{cl100k_base, o200k_base, p50k_base, p50k_edit, r50k_base, CoreBPE};
+
+/// The error returned when a tokenizer definition cannot be loaded.
+pub type LoadError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The error returned when a tokenizer cannot tokenize a text.
+pub type TokenizeError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A tokenizer used to count tokens.
+#[derive(Clone)]
+pub enum Tokenizer {
+    /// One of the tiktoken encodings bundled with `tc`.
+    Model(TokenizerModel),
+    /// A tokenizer loaded from a HuggingFace `tokenizer.json` file.
+    HuggingFace(Arc<tokenizers::Tokenizer>),
+    /// A tokenizer loaded from a SentencePiece `.model` file.
+    SentencePiece(Arc<SentencePieceModel>),
+    /// A tiktoken encoding built from a local rank file.
+    Encoding(Arc<CoreBPE>),
+}
+
+impl Tokenizer {
+    /// Loads a HuggingFace `tokenizer.json` file.
+    ///
+    /// Truncation and padding settings in the file are dropped, since they
+    /// fit model inputs to a length rather than tokenize the text.
+    pub fn from_hugging_face_file(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let mut tokenizer = tokenizers::Tokenizer::from_file(path)?;
+        tokenizer.with_truncation(None)?.with_padding(None);
+        Ok(Tokenizer::HuggingFace(Arc::new(tokenizer)))
+    }
+
+    /// Loads a SentencePiece `.model` file.
+    pub fn from_sentencepiece_file(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let model = SentencePieceModel::from_bytes(&fs::read(path)?)?;
+        Ok(Tokenizer::SentencePiece(Arc::new(model)))
+    }
+
+    /// Builds a tiktoken encoding from a `.tiktoken` rank file, the regex
+    /// used to split text before merging, and an optional JSON file mapping
+    /// special tokens to their ranks. Special tokens found in the text count
+    /// as one token each.
+    ///
+    /// Each line of the rank file holds a base64-encoded token and its rank.
+    /// All 256 single bytes must have a rank, so that any text can be
+    /// encoded.
+    pub fn from_encoding_files(
+        ranks_path: impl AsRef<Path>,
+        pattern: &str,
+        special_path: Option<&Path>,
+    ) -> Result<Self, LoadError> {
+        let ranks_file = fs::read_to_string(ranks_path)?;
+        let mut encoder = FxHashMap::default();
+        let mut ranks = HashSet::new();
+        for (number, line) in ranks_file.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid_line = || format!("invalid rank on line {}", number + 1);
+            let (token, rank) = line.split_once(' ').ok_or_else(invalid_line)?;
+            let token = general_purpose::STANDARD.decode(token)?;
+            let rank: usize = rank.trim().parse().map_err(|_| invalid_line())?;
+            if !ranks.insert(rank) || encoder.insert(token, rank).is_some() {
+                return Err(format!("duplicate token or rank on line {}", number + 1).into());
+            }
+        }
+        if (0..=u8::MAX).any(|byte| !encoder.contains_key(&vec![byte])) {
+            return Err("rank file does not cover all single bytes".into());
+        }
+
+        let special_tokens: FxHashMap<String, usize> = match special_path {
+            Some(path) => serde_json::from_slice(&fs::read(path)?)?,
+            None => FxHashMap::default(),
+        };
+
+        let bpe = CoreBPE::new(encoder, special_tokens, pattern)?;
+        Ok(Tokenizer::Encoding(Arc::new(bpe)))
+    }
+
+    /// Counts the tokens of `text`, without adding any special tokens.
+    ///
+    /// Only HuggingFace tokenizers can fail, when their model cannot
+    /// represent the text, such as a vocabulary without an unknown token.
+    pub fn count_tokens(&self, text: &str) -> Result<usize, TokenizeError> {
+        Ok(match self {
+            Tokenizer::Model(model) => model.bpe().encode_ordinary(text).len(),
+            Tokenizer::HuggingFace(tokenizer) => tokenizer.encode_fast(text, false)?.len(),
+            Tokenizer::SentencePiece(model) => model.count_pieces(text),
+            Tokenizer::Encoding(bpe) => bpe.encode_with_special_tokens(text).len(),
+        })
+    }
+
+    /// Whether text can be tokenized in pieces cut where no token spans, as
+    /// described in [`Counter::count_reader`](crate::Counter::count_reader).
+    ///
+    /// Only the bundled encodings are known to allow this. Other tokenizers,
+    /// including encodings with a custom pattern, may normalize or
+    /// pre-tokenize text differently, so they get the whole input at once.
+    pub(crate) fn supports_chunking(&self) -> bool {
+        matches!(self, Tokenizer::Model(_))
+    }
+}
+
+impl From<TokenizerModel> for Tokenizer {
+    fn from(model: TokenizerModel) -> Self {
+        Tokenizer::Model(model)
+    }
+}
+
+impl fmt::Debug for Tokenizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tokenizer::Model(model) => f.debug_tuple("Model").field(model).finish(),
+            Tokenizer::HuggingFace(_) => f.write_str("HuggingFace"),
+            Tokenizer::SentencePiece(_) => f.write_str("SentencePiece"),
+            Tokenizer::Encoding(_) => f.write_str("Encoding"),
+        }
+    }
+}
+
+/// The tiktoken encodings available for counting tokens.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum TokenizerModel {
+    /// `r50k_base`
+    GPT3,
+    /// `p50k_edit`
+    Edit,
+    /// `p50k_base`
+    Code,
+    /// `cl100k_base`
+    #[clap(name = "chatgpt")]
+    ChatGPT,
+    /// `o200k_base`
+    GPT4O,
+}
+
+impl TokenizerModel {
+    /// Returns the encoder for this model.
+    ///
+    /// Building an encoder parses its whole rank table, so each one is built
+    /// on first use and then shared by every caller, on every thread.
+    pub(crate) fn bpe(self) -> &'static CoreBPE {
+        static GPT3: OnceLock<CoreBPE> = OnceLock::new();
+        static EDIT: OnceLock<CoreBPE> = OnceLock::new();
+        static CODE: OnceLock<CoreBPE> = OnceLock::new();
+        static CHATGPT: OnceLock<CoreBPE> = OnceLock::new();
+        static GPT4O: OnceLock<CoreBPE> = OnceLock::new();
+
+        match self {
+            TokenizerModel::GPT3 => GPT3.get_or_init(|| r50k_base().unwrap()),
+            TokenizerModel::Edit => EDIT.get_or_init(|| p50k_edit().unwrap()),
+            TokenizerModel::Code => CODE.get_or_init(|| p50k_base().unwrap()),
+            TokenizerModel::ChatGPT => CHATGPT.get_or_init(|| cl100k_base().unwrap()),
+            TokenizerModel::GPT4O => GPT4O.get_or_init(|| o200k_base().unwrap()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::thread;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_bpe_is_built_once() {
+        let first = TokenizerModel::GPT3.bpe();
+        let second = TokenizerModel::GPT3.bpe();
+        assert!(std::ptr::eq(first, second));
+    }
+
+    #[test]
+    fn test_bpe_is_shared_across_threads() {
+        let addresses: Vec<usize> = (0..4)
+            .map(|_| thread::spawn(|| TokenizerModel::Code.bpe() as *const CoreBPE as usize))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        assert!(addresses.windows(2).all(|pair| pair[0] == pair[1]));
+        assert_eq!(
+            TokenizerModel::Code
+                .bpe()
+                .encode_ordinary("Hello, world!")
+                .len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_hugging_face_file() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .write_all(
+                br#"{
+                    "version": "1.0",
+                    "added_tokens": [],
+                    "normalizer": {"type": "Lowercase"},
+                    "pre_tokenizer": {"type": "Whitespace"},
+                    "model": {
+                        "type": "WordLevel",
+                        "vocab": {"hello": 0, "world": 1, "[UNK]": 2},
+                        "unk_token": "[UNK]"
+                    }
+                }"#,
+            )
+            .unwrap();
+        let tokenizer = Tokenizer::from_hugging_face_file(temp_file.path()).unwrap();
+        assert_eq!(tokenizer.count_tokens("Hello, world!\nfoo bar").unwrap(), 6);
+        assert!(!tokenizer.supports_chunking());
+    }
+
+    fn write_word_level(settings: &str, unk_token: &str) -> NamedTempFile {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(
+            temp_file,
+            r#"{{
+                "version": "1.0",
+                "added_tokens": [],
+                {settings}
+                "pre_tokenizer": {{"type": "Whitespace"}},
+                "model": {{
+                    "type": "WordLevel",
+                    "vocab": {{"a": 0, "b": 1, "[PAD]": 2}},
+                    "unk_token": "{unk_token}"
+                }}
+            }}"#
+        )
+        .unwrap();
+        temp_file
+    }
+
+    #[test]
+    fn test_hugging_face_truncation_and_padding_are_ignored() {
+        let truncated = write_word_level(
+            r#""truncation": {"direction": "Right", "max_length": 3, "strategy": "LongestFirst", "stride": 0},"#,
+            "[PAD]",
+        );
+        let tokenizer = Tokenizer::from_hugging_face_file(truncated.path()).unwrap();
+        assert_eq!(tokenizer.count_tokens(&"a b ".repeat(5)).unwrap(), 10);
+
+        let padded = write_word_level(
+            r#""padding": {"strategy": {"Fixed": 16}, "direction": "Right", "pad_to_multiple_of": null, "pad_id": 2, "pad_type_id": 0, "pad_token": "[PAD]"},"#,
+            "[PAD]",
+        );
+        let tokenizer = Tokenizer::from_hugging_face_file(padded.path()).unwrap();
+        assert_eq!(tokenizer.count_tokens("a b").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_hugging_face_encode_error() {
+        let temp_file = write_word_level("", "[UNK]");
+        let tokenizer = Tokenizer::from_hugging_face_file(temp_file.path()).unwrap();
+        assert_eq!(tokenizer.count_tokens("a b").unwrap(), 2);
+        assert!(tokenizer.count_tokens("a c").is_err());
+    }
+
+    #[test]
+    fn test_invalid_hugging_face_file() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(b"{\"model\": 42}").unwrap();
+        assert!(Tokenizer::from_hugging_face_file(temp_file.path()).is_err());
+        assert!(Tokenizer::from_hugging_face_file("does/not/exist.json").is_err());
+    }
+
+    fn write_ranks(extra: &[&[u8]]) -> NamedTempFile {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        let tokens = (0..=u8::MAX)
+            .map(|byte| vec![byte])
+            .chain(extra.iter().map(|t| t.to_vec()));
+        for (rank, token) in tokens.enumerate() {
+            writeln!(
+                temp_file,
+                "{} {}",
+                general_purpose::STANDARD.encode(token),
+                rank
+            )
+            .unwrap();
+        }
+        temp_file
+    }
+
+    #[test]
+    fn test_encoding_files() {
+        let ranks = write_ranks(&[b"he", b"ll", b"hell", b"llo", b" w"]);
+        let mut special = NamedTempFile::new().unwrap();
+        special.write_all(br#"{"<|end|>": 300}"#).unwrap();
+        let tokenizer = Tokenizer::from_encoding_files(
+            ranks.path(),
+            r" ?\w+| ?[^\s\w]+|\s+(?!\S)|\s+",
+            Some(special.path()),
+        )
+        .unwrap();
+        // "hell" + "o", "," and " w" + "o" + "r" + "l" + "d".
+        assert_eq!(tokenizer.count_tokens("hello, world").unwrap(), 8);
+        assert_eq!(tokenizer.count_tokens("<|end|>").unwrap(), 1);
+        assert_eq!(tokenizer.count_tokens("hello<|end|>").unwrap(), 3);
+        assert!(!tokenizer.supports_chunking());
+    }
+
+    #[test]
+    fn test_invalid_encoding_files() {
+        let pattern = r"\S+|\s+";
+        let ranks = write_ranks(&[b"he", b"he"]);
+        assert!(Tokenizer::from_encoding_files(ranks.path(), pattern, None).is_err());
+
+        let mut ranks = NamedTempFile::new().unwrap();
+        ranks.write_all(b"aGU= 0\n").unwrap();
+        assert!(Tokenizer::from_encoding_files(ranks.path(), pattern, None).is_err());
+
+        let ranks = write_ranks(&[]);
+        assert!(Tokenizer::from_encoding_files(ranks.path(), "(", None).is_err());
+        let mut special = NamedTempFile::new().unwrap();
+        special.write_all(b"[1, 2]").unwrap();
+        assert!(
+            Tokenizer::from_encoding_files(ranks.path(), pattern, Some(special.path())).is_err()
+        );
+    }
+}
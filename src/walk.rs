@@ -0,0 +1,221 @@
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
+
+/// Name of the ignore files read only by `tc`, with the syntax of
+/// `.gitignore`.
+const IGNORE_FILENAME: &str = ".tcignore";
+
+/// How directories given as inputs are walked and which files are counted.
+#[derive(Debug, Default, Clone)]
+pub struct WalkOptions {
+    /// Whether directories are replaced by the files inside them.
+    pub recursive: bool,
+    /// How many levels below each directory are walked, without a limit
+    /// when `None`.
+    pub max_depth: Option<usize>,
+    /// Whether hidden files and directories are walked.
+    pub hidden: bool,
+    /// Whether files excluded by `.gitignore`, `.ignore`, `.tcignore` or
+    /// the global git excludes are walked too.
+    pub no_ignore: bool,
+    /// Globs a file must match one of to be counted, or `None` to count
+    /// every file.
+    pub include: Option<GlobSet>,
+    /// Globs of files that are not counted, and of directories that are
+    /// not walked.
+    pub exclude: GlobSet,
+}
+
+impl WalkOptions {
+    /// Returns whether the file at `path` passes the include and exclude
+    /// globs. Paths are matched without a leading `./`.
+    fn is_selected(&self, path: &Path) -> bool {
+        let path = path.strip_prefix(".").unwrap_or(path);
+        self.include
+            .as_ref()
+            .is_none_or(|include| include.is_match(path))
+            && !self.exclude.is_match(path)
+    }
+}
+
+/// A file to count, or an error met while walking a directory.
+pub(crate) struct Input {
+    pub(crate) name: String,
+    pub(crate) error: Option<ignore::Error>,
+}
+
+/// Returns the inputs to count for the `files` given on the command line.
+///
+/// When walking recursively, each directory is replaced by the regular
+/// files inside it, sorted by name so the output does not depend on the
+/// file system. Ignore files are honoured the way ripgrep does, so
+/// `.gitignore` only applies inside a git repository. Other files are kept
+/// as they are, even if they are hidden or ignored.
+///
+/// Files are left out unless they pass the include and exclude globs,
+/// matched against their path below the directory being walked, or as
+/// given for other files. Directories matching an exclude glob are not
+/// walked.
+pub(crate) fn expand_inputs(files: &[String], options: &WalkOptions) -> Vec<Input> {
+    let mut inputs = Vec::new();
+    for file in files {
+        let is_dir = Path::new(file).is_dir();
+        if !is_dir && !options.is_selected(Path::new(file)) {
+            continue;
+        }
+        if !options.recursive || !is_dir {
+            inputs.push(Input {
+                name: file.clone(),
+                error: None,
+            });
+            continue;
+        }
+        let mut builder = WalkBuilder::new(file);
+        builder
+            .standard_filters(!options.no_ignore)
+            .hidden(!options.hidden)
+            .max_depth(options.max_depth)
+            .sort_by_file_name(|a, b| a.cmp(b));
+        if !options.no_ignore {
+            builder.add_custom_ignore_filename(IGNORE_FILENAME);
+        }
+        let root = PathBuf::from(file);
+        let exclude = options.exclude.clone();
+        let filter_root = root.clone();
+        builder.filter_entry(move |entry| {
+            entry.depth() == 0
+                || !entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_dir())
+                || !exclude.is_match(relative_path(entry.path(), &filter_root))
+        });
+        for entry in builder.build() {
+            match entry {
+                Ok(entry) => {
+                    let is_file = entry
+                        .file_type()
+                        .is_some_and(|file_type| file_type.is_file());
+                    if is_file && options.is_selected(relative_path(entry.path(), &root)) {
+                        inputs.push(Input {
+                            name: entry.path().to_string_lossy().into_owned(),
+                            error: None,
+                        });
+                    }
+                }
+                Err(err) => inputs.push(Input {
+                    name: error_path(&err).unwrap_or(file).to_string(),
+                    error: Some(err),
+                }),
+            }
+        }
+    }
+    inputs
+}
+
+/// Returns `path` relative to the directory `root` it was found in.
+fn relative_path<'a>(path: &'a Path, root: &Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+/// Returns the path a walking error happened at, if it has one.
+fn error_path(err: &ignore::Error) -> Option<&str> {
+    match err {
+        ignore::Error::WithPath { path, .. } => path.to_str(),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
+        }
+        ignore::Error::Partial(errs) if errs.len() == 1 => error_path(&errs[0]),
+        _ => None,
+    }
+}
+
+/// Parses an `--include` or `--exclude` pattern. A pattern without a `/`
+/// matches file names in any directory, others match paths below the
+/// directory being walked, in which `*` does not cross a `/`.
+pub(crate) fn parse_glob(pattern: &str) -> Result<Glob, globset::Error> {
+    let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+    if pattern.contains('/') {
+        return GlobBuilder::new(pattern).literal_separator(true).build();
+    }
+    // Parsed on its own first so that errors quote the pattern as given.
+    Glob::new(pattern)?;
+    GlobBuilder::new(&format!("**/{pattern}"))
+        .literal_separator(true)
+        .build()
+}
+
+/// Returns a set matching any of `globs`. A glob ending in `/**` also
+/// matches the directory it names, so that the directory is not walked.
+pub(crate) fn glob_set(globs: &[Glob]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(glob.clone());
+        if let Some(dir) = glob.glob().strip_suffix("/**") {
+            builder.add(GlobBuilder::new(dir).literal_separator(true).build()?);
+        }
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn globs(patterns: &[&str]) -> GlobSet {
+        let globs: Vec<Glob> = patterns
+            .iter()
+            .map(|pattern| parse_glob(pattern).unwrap())
+            .collect();
+        glob_set(&globs).unwrap()
+    }
+
+    #[test]
+    fn test_glob_selection() {
+        let options = WalkOptions {
+            include: Some(globs(&["*.rs"])),
+            exclude: globs(&["./tests/**", "src/*_generated.rs"]),
+            ..WalkOptions::default()
+        };
+        assert!(options.is_selected(Path::new("main.rs")));
+        assert!(options.is_selected(Path::new("./src/lib.rs")));
+        assert!(options.is_selected(Path::new("src/nested/mod_generated.rs")));
+        assert!(!options.is_selected(Path::new("src/parser_generated.rs")));
+        assert!(!options.is_selected(Path::new("./tests/cli.rs")));
+        assert!(!options.is_selected(Path::new("README.md")));
+    }
+
+    #[test]
+    fn test_parse_glob_errors() {
+        assert!(parse_glob("[").is_err());
+        assert!(parse_glob("src/[").is_err());
+    }
+
+    #[test]
+    fn test_excluded_directories_are_not_walked() {
+        let dir = tempdir().unwrap();
+        for name in ["src/lib.rs", "tests/cli.rs", "target/debug/out.rs"] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let root = dir.path().to_string_lossy().into_owned();
+        let options = WalkOptions {
+            recursive: true,
+            exclude: globs(&["tests/**", "target"]),
+            ..WalkOptions::default()
+        };
+        assert!(options.exclude.is_match("tests"));
+
+        let names: Vec<String> = expand_inputs(&[root], &options)
+            .into_iter()
+            .map(|input| input.name)
+            .collect();
+        assert_eq!(
+            names,
+            [dir.path().join("src").join("lib.rs").to_string_lossy()]
+        );
+    }
+}
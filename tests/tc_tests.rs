@@ -2,15 +2,13 @@ use assert_cmd::Command;
 use predicates::prelude::*;
 use std::fs::{self, File};
 use std::io::Write;
+use std::path::Path;
 use tempfile::tempdir;
 
 #[test]
 fn test_empty_input() {
     let mut cmd = Command::cargo_bin("tc").unwrap();
-    cmd.write_stdin("")
-        .assert()
-        .success()
-        .stdout("       0        0        0        0\n");
+    cmd.write_stdin("").assert().success().stdout("0 0 0 0\n");
 }
 
 #[test]
@@ -19,7 +17,7 @@ fn test_single_word() {
     cmd.write_stdin("hello")
         .assert()
         .success()
-        .stdout("       1        1        5        1\n");
+        .stdout("1 1 5 1\n");
 }
 
 #[test]
@@ -28,7 +26,7 @@ fn test_multiple_words() {
     cmd.write_stdin("hello world\nrust is great")
         .assert()
         .success()
-        .stdout("       2        5       25        6\n");
+        .stdout(" 2  5 25  6\n");
 }
 
 #[test]
@@ -43,9 +41,7 @@ fn test_file_input() {
     cmd.arg(file_path)
         .assert()
         .success()
-        .stdout(predicate::str::contains(
-            "       2        6       30       11",
-        ));
+        .stdout(predicate::str::contains(" 2  6 30 11"));
 }
 
 #[test]
@@ -65,19 +61,64 @@ fn test_multiple_files() {
         .arg(&file2_path)
         .assert()
         .success()
-        .stdout(predicate::str::contains(
-            "       1        2       14        5",
-        ))
-        .stdout(predicate::str::contains(
-            "       1        4       22        6",
-        ))
-        .stdout(predicate::str::contains(
-            "       2        6       36       11 total",
-        ));
+        .stdout(predicate::str::contains(" 1  2 14  5"))
+        .stdout(predicate::str::contains(" 1  4 22  6"))
+        .stdout(predicate::str::contains(" 2  6 36 11 total"));
     fs::remove_file(file1_path).unwrap();
     fs::remove_file(file2_path).unwrap();
 }
 
+#[test]
+fn test_multiple_tokenizer_models() {
+    let dir = tempdir().unwrap();
+    let file1_path = dir.path().join("test1.txt");
+    fs::write(&file1_path, "Hello, world!").unwrap();
+    let file2_path = dir.path().join("test2.txt");
+    fs::write(&file2_path, "    indented code\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-t", "--model", "gpt3,chatgpt", "--model", "gpt4o"])
+        .arg(&file1_path)
+        .arg(&file2_path)
+        .assert()
+        .success()
+        .stdout(format!(
+            " 4  4  4 {}\n 7  5  5 {}\n11  9  9 total\n",
+            file1_path.display(),
+            file2_path.display()
+        ));
+}
+
+#[test]
+fn test_parallel_jobs() {
+    let dir = tempdir().unwrap();
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-j", "3"]);
+    for i in 1..=5 {
+        let file_path = dir.path().join(format!("test{}.txt", i));
+        let mut file = File::create(&file_path).unwrap();
+        write!(file, "{}", "word ".repeat(i)).unwrap();
+        cmd.arg(&file_path);
+    }
+    cmd.arg("-w").assert().success().stdout(format!(
+        " 1 {}\n 2 {}\n 3 {}\n 4 {}\n 5 {}\n15 total\n",
+        dir.path().join("test1.txt").display(),
+        dir.path().join("test2.txt").display(),
+        dir.path().join("test3.txt").display(),
+        dir.path().join("test4.txt").display(),
+        dir.path().join("test5.txt").display(),
+    ));
+}
+
+#[test]
+fn test_zero_jobs() {
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-j", "0"])
+        .write_stdin("hello")
+        .assert()
+        .failure();
+}
+
 #[test]
 fn test_non_existent_file() {
     let mut cmd = Command::cargo_bin("tc").unwrap();
@@ -92,11 +133,11 @@ fn test_non_existent_file() {
 #[test]
 fn test_specific_options() {
     let mut cmd = Command::cargo_bin("tc").unwrap();
-    cmd.args(&["-lw"])
+    cmd.args(["-lw"])
         .write_stdin("Hello\nWorld\nRust")
         .assert()
         .success()
-        .stdout("       3        3\n");
+        .stdout("3 3\n");
 }
 
 #[test]
@@ -105,17 +146,565 @@ fn test_utf8_input() {
     cmd.write_stdin("Hello, 世界!")
         .assert()
         .success()
-        .stdout("       1        2       10        8\n");
+        .stdout(" 1  2 10  8\n");
+}
+
+#[test]
+fn test_bytes_and_chars() {
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-mb"])
+        .write_stdin("Hello, 世界!\n")
+        .assert()
+        .success()
+        .stdout("11 15\n");
+}
+
+#[test]
+fn test_bytes_of_invalid_utf8() {
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--chars", "--bytes"])
+        .write_stdin(b"caf\xc3\xa9 \xff\xfe".as_slice())
+        .assert()
+        .success()
+        .stdout("7 8\n");
+}
+
+#[test]
+fn test_max_line_length_total_is_max() {
+    let dir = tempdir().unwrap();
+    let file1_path = dir.path().join("test1.txt");
+    fs::write(&file1_path, "short\n\tindented\n").unwrap();
+    let file2_path = dir.path().join("test2.txt");
+    fs::write(&file2_path, "こんにちは\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-lL"])
+        .arg(&file1_path)
+        .arg(&file2_path)
+        .assert()
+        .success()
+        .stdout(format!(
+            " 2 16 {}\n 1 10 {}\n 3 16 total\n",
+            file1_path.display(),
+            file2_path.display()
+        ));
+}
+
+#[test]
+fn test_graphemes() {
+    let dir = tempdir().unwrap();
+    let file1_path = dir.path().join("test1.txt");
+    fs::write(&file1_path, "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}").unwrap();
+    let file2_path = dir.path().join("test2.txt");
+    fs::write(&file2_path, "cafe\u{301}\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-cg"])
+        .arg(&file1_path)
+        .arg(&file2_path)
+        .assert()
+        .success()
+        .stdout(format!(
+            " 5  1 {}\n 6  5 {}\n11  6 total\n",
+            file1_path.display(),
+            file2_path.display()
+        ));
+}
+
+#[test]
+fn test_sentences_and_paragraphs() {
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-sp"])
+        .write_stdin("This sentence is\nwrapped. Short one!\n\n第二段。还有一句！\n")
+        .assert()
+        .success()
+        .stdout("4 2\n");
+}
+
+#[test]
+fn test_unicode_word_mode() {
+    let input = "hello\u{a0}world\u{3000}foo — bar!\n";
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.arg("-w")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("3\n");
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-w", "--word-mode", "unicode"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("4\n");
+}
+
+#[test]
+fn test_cjk_word_mode() {
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-w", "--word-mode", "cjk"])
+        .write_stdin("我来到北京清华大学。")
+        .assert()
+        .success()
+        .stdout("4\n");
+}
+
+#[test]
+fn test_json_format() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("test.txt");
+    fs::write(&file_path, "Hello, world!\n").unwrap();
+    let missing_path = dir.path().join("missing.txt");
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    let output = cmd
+        .args(["--format", "json", "-lw", "-t", "--model", "gpt3,gpt4o"])
+        .arg(&file_path)
+        .arg(&missing_path)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let tokens = serde_json::json!([
+        { "model": "gpt3", "count": 5 },
+        { "model": "gpt4o", "count": 4 },
+    ]);
+    assert_eq!(
+        document,
+        serde_json::json!({
+            "schema_version": 1,
+            "inputs": [
+                {
+                    "path": file_path.to_str().unwrap(),
+                    "lines": 1,
+                    "words": 2,
+                    "tokens": tokens,
+                },
+                {
+                    "path": missing_path.to_str().unwrap(),
+                    "error": "No such file or directory (os error 2)",
+                },
+            ],
+            "total": { "lines": 1, "words": 2, "tokens": tokens },
+        })
+    );
+}
+
+#[test]
+fn test_json_format_stdin() {
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    let output = cmd
+        .args(["--format", "json", "-c"])
+        .write_stdin("hello")
+        .output()
+        .unwrap();
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        document,
+        serde_json::json!({
+            "schema_version": 1,
+            "inputs": [{ "path": null, "chars": 5 }],
+            "total": null,
+        })
+    );
+}
+
+#[test]
+fn test_ndjson_format() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("test.txt");
+    fs::write(&file_path, "Hello, world!\n").unwrap();
+    let missing_path = dir.path().join("missing.txt");
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--format", "ndjson", "-j", "1", "-w"])
+        .arg(&file_path)
+        .arg(&missing_path)
+        .arg(&file_path)
+        .assert()
+        .failure()
+        .stdout(format!(
+            "{{\"type\":\"input\",\"path\":{path:?},\"words\":2}}\n\
+             {{\"type\":\"error\",\"path\":{missing:?},\"error\":\"No such file or directory (os error 2)\"}}\n\
+             {{\"type\":\"input\",\"path\":{path:?},\"words\":2}}\n\
+             {{\"type\":\"total\",\"words\":4}}\n",
+            path = file_path.to_str().unwrap(),
+            missing = missing_path.to_str().unwrap(),
+        ));
+}
+
+#[test]
+fn test_ndjson_format_parallel() {
+    let dir = tempdir().unwrap();
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--format", "ndjson", "-j", "4", "-w"]);
+    for i in 1..=8 {
+        let file_path = dir.path().join(format!("test{}.txt", i));
+        fs::write(&file_path, "word ".repeat(i * 1000)).unwrap();
+        cmd.arg(&file_path);
+    }
+    let output = cmd.output().unwrap();
+    let records: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 9);
+    let mut words: Vec<u64> = records[..8]
+        .iter()
+        .map(|record| {
+            assert_eq!(record["type"], "input");
+            record["words"].as_u64().unwrap()
+        })
+        .collect();
+    words.sort();
+    assert_eq!(words, (1..=8).map(|i| i * 1000).collect::<Vec<_>>());
+    assert_eq!(
+        records[8],
+        serde_json::json!({ "type": "total", "words": 36000 })
+    );
+}
+
+#[test]
+fn test_markdown_format() {
+    let dir = tempdir().unwrap();
+    let file1_path = dir.path().join("a|b.txt");
+    fs::write(&file1_path, "Hello, world!\n").unwrap();
+    let file2_path = dir.path().join("c.txt");
+    fs::write(&file2_path, "one two three\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--format", "markdown", "-lw", "-t", "--model", "gpt4o"])
+        .env("LC_ALL", "de_DE.UTF-8")
+        .arg(&file1_path)
+        .arg(&file2_path)
+        .assert()
+        .success()
+        .stdout(format!(
+            "| Zeilen | Wörter | Tokens(gpt4o) | Datei |\n\
+             | ---: | ---: | ---: | :--- |\n\
+             | 1 | 2 | 4 | {} |\n\
+             | 1 | 3 | 4 | {} |\n\
+             | **2** | **5** | **8** | **Gesamt** |\n",
+            file1_path.display().to_string().replace('|', "\\|"),
+            file2_path.display(),
+        ));
+}
+
+#[test]
+fn test_header() {
+    let dir = tempdir().unwrap();
+    let file1_path = dir.path().join("test1.txt");
+    fs::write(&file1_path, "Hello, world!\n").unwrap();
+    let file2_path = dir.path().join("test2.txt");
+    fs::write(&file2_path, "one two three\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--header", "-lw", "-t", "--model", "gpt4o"])
+        .env("LC_ALL", "en_US.UTF-8")
+        .arg(&file1_path)
+        .arg(&file2_path)
+        .assert()
+        .success()
+        .stdout(format!(
+            "LINES WORDS TOKENS(gpt4o) FILE\n    \
+                 1     2             4 {}\n    \
+                 1     3             4 {}\n    \
+                 2     5             8 total\n",
+            file1_path.display(),
+            file2_path.display(),
+        ));
+}
+
+#[test]
+fn test_header_localized() {
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--header", "-lw"])
+        .env("LC_ALL", "ja_JP.UTF-8")
+        .write_stdin("こんにちは 世界\n")
+        .assert()
+        .success()
+        .stdout("行数 単語数 ファイル\n   1      2\n");
+}
+
+#[test]
+fn test_header_labels_are_single_words() {
+    for (locale, header) in [
+        ("en_US.UTF-8", "LINES MAXLINE FILE"),
+        ("de_DE.UTF-8", "ZEILEN MAXZEILE DATEI"),
+        ("ja_JP.UTF-8", "行数 最大行幅 ファイル"),
+    ] {
+        let mut cmd = Command::cargo_bin("tc").unwrap();
+        let output = cmd
+            .args(["--header", "-lL"])
+            .env("LC_ALL", locale)
+            .write_stdin("hello\n")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let first_line = stdout.lines().next().unwrap();
+        assert_eq!(
+            first_line.split_whitespace().collect::<Vec<_>>().join(" "),
+            header
+        );
+    }
+}
+
+#[test]
+fn test_csv_format() {
+    let dir = tempdir().unwrap();
+    let file1_path = dir.path().join("plain.txt");
+    fs::write(&file1_path, "Hello, world!\n").unwrap();
+    let file2_path = dir.path().join("with,\"quotes\".txt");
+    fs::write(&file2_path, "one two three\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--format", "csv", "-lw", "-t", "--model", "gpt3,gpt4o"])
+        .arg(&file1_path)
+        .arg(&file2_path)
+        .assert()
+        .success()
+        .stdout(format!(
+            "lines,words,tokens(gpt3),tokens(gpt4o),path\n\
+             1,2,5,4,{}\n\
+             1,3,4,4,\"{}\"\n\
+             2,5,9,8,total\n",
+            file1_path.display(),
+            file2_path.display().to_string().replace('"', "\"\""),
+        ));
+}
+
+#[test]
+fn test_tsv_format_without_total() {
+    let dir = tempdir().unwrap();
+    let file1_path = dir.path().join("a,b.txt");
+    fs::write(&file1_path, "one two\n").unwrap();
+    let file2_path = dir.path().join("c.txt");
+    fs::write(&file2_path, "three\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--format", "tsv", "--total", "never", "-w"])
+        .arg(&file1_path)
+        .arg(&file2_path)
+        .assert()
+        .success()
+        .stdout(format!(
+            "words\tpath\n2\t{}\n1\t{}\n",
+            file1_path.display(),
+            file2_path.display()
+        ));
+}
+
+#[test]
+fn test_total_always() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("test.txt");
+    fs::write(&file_path, "one two\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--total", "always", "-lw"])
+        .env("LC_ALL", "en_US.UTF-8")
+        .arg(&file_path)
+        .assert()
+        .success()
+        .stdout(format!("1 2 {}\n1 2 total\n", file_path.display()));
+}
+
+#[test]
+fn test_total_only() {
+    let dir = tempdir().unwrap();
+    let file1_path = dir.path().join("test1.txt");
+    fs::write(&file1_path, "one two\n").unwrap();
+    let file2_path = dir.path().join("test2.txt");
+    fs::write(&file2_path, "three four five\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--total", "only", "-w"])
+        .arg(&file1_path)
+        .arg(&file2_path)
+        .assert()
+        .success()
+        .stdout("5\n");
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    let output = cmd
+        .args(["--total", "only", "--format", "json", "-w"])
+        .arg(&file1_path)
+        .arg(&file2_path)
+        .output()
+        .unwrap();
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(document["inputs"], serde_json::json!([]));
+    assert_eq!(document["total"], serde_json::json!({ "words": 5 }));
 }
 
 #[test]
 fn test_different_tokenizer_model() {
     let mut cmd = Command::cargo_bin("tc").unwrap();
-    cmd.args(&["--model", "gpt4o"])
+    cmd.args(["--model", "gpt4o"])
         .write_stdin("Hello, world!")
         .assert()
         .success()
-        .stdout("       1        2       13        4\n");
+        .stdout(" 1  2 13  4\n");
+}
+
+const WORD_LEVEL_TOKENIZER: &str = r#"{
+    "version": "1.0",
+    "added_tokens": [],
+    "pre_tokenizer": {"type": "Whitespace"},
+    "model": {
+        "type": "WordLevel",
+        "vocab": {"hello": 0, "world": 1, "[UNK]": 2},
+        "unk_token": "[UNK]"
+    }
+}"#;
+
+#[test]
+fn test_tokenizer_file() {
+    let dir = tempdir().unwrap();
+    let tokenizer_path = dir.path().join("tokenizer.json");
+    fs::write(&tokenizer_path, WORD_LEVEL_TOKENIZER).unwrap();
+    let file1_path = dir.path().join("test1.txt");
+    fs::write(&file1_path, "hello world").unwrap();
+    let file2_path = dir.path().join("test2.txt");
+    fs::write(&file2_path, "hello, tokenizer!\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.arg("--tokenizer-file")
+        .arg(&tokenizer_path)
+        .arg(&file1_path)
+        .arg(&file2_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(" 1  2 11  2"))
+        .stdout(predicate::str::contains(" 1  2 18  4"))
+        .stdout(predicate::str::contains(" 2  4 29  6 total"));
+}
+
+#[test]
+fn test_tokenizer_file_encode_error() {
+    let dir = tempdir().unwrap();
+    let tokenizer_path = dir.path().join("tokenizer.json");
+    // The unknown token is missing from the vocabulary, so unknown words
+    // cannot be encoded.
+    fs::write(
+        &tokenizer_path,
+        r#"{"version": "1.0", "added_tokens": [],
+            "pre_tokenizer": {"type": "Whitespace"},
+            "model": {"type": "WordLevel", "vocab": {"a": 0}, "unk_token": "[UNK]"}}"#,
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-t", "--tokenizer-file"])
+        .arg(&tokenizer_path)
+        .env("LC_ALL", "en_US.UTF-8")
+        .write_stdin("a b")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("")
+        .stderr(predicate::str::contains("tc: -: Error counting tokens"));
+}
+
+#[test]
+fn test_invalid_tokenizer_file() {
+    let dir = tempdir().unwrap();
+    let tokenizer_path = dir.path().join("tokenizer.json");
+    fs::write(&tokenizer_path, "not json").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.arg("--tokenizer-file")
+        .arg(&tokenizer_path)
+        .write_stdin("hello")
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("Error loading tokenizer"));
+}
+
+#[test]
+fn test_sentencepiece_normalization_rules() {
+    // A unigram model with the nmt_nfkc rules SentencePiece trains models
+    // with by default and a tiny vocabulary holding "▁hello", "▁world",
+    // "▁fi", "▁café", "▁" and single letters. See tests/fixtures/README.md.
+    let model = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("nmt_nfkc.model");
+    for (input, expected) in [
+        // Full-width letters and the ideographic space are folded.
+        ("ｈｅｌｌｏ\u{3000}ｗｏｒｌｄ", "2\n"),
+        // Tabs, newlines and zero-width joiners become collapsed spaces.
+        ("  hello\t\n\u{200d}world ", "2\n"),
+        // The "ﬁ" ligature is decomposed, "é" is composed.
+        ("\u{fb01}", "1\n"),
+        ("cafe\u{301}", "1\n"),
+        // "①②" becomes "▁", "1", "2".
+        ("①②", "3\n"),
+    ] {
+        let mut cmd = Command::cargo_bin("tc").unwrap();
+        cmd.arg("-t")
+            .arg("--sentencepiece-model")
+            .arg(&model)
+            .write_stdin(input)
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+}
+
+#[test]
+fn test_invalid_sentencepiece_model() {
+    let dir = tempdir().unwrap();
+    let model_path = dir.path().join("tokenizer.model");
+    fs::write(&model_path, WORD_LEVEL_TOKENIZER).unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.arg("--sentencepiece-model")
+        .arg(&model_path)
+        .write_stdin("hello")
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("Error loading tokenizer"));
+}
+
+#[test]
+fn test_encoding_file_requires_pattern() {
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--encoding-file", "ranks.tiktoken"])
+        .write_stdin("hello")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--pattern"));
+}
+
+#[test]
+fn test_encoding_file_special_tokens() {
+    use base64::{engine::general_purpose, Engine as _};
+
+    let dir = tempdir().unwrap();
+    let ranks_path = dir.path().join("bytes.tiktoken");
+    let ranks: String = (0..=u8::MAX)
+        .map(|byte| format!("{} {byte}\n", general_purpose::STANDARD.encode([byte])))
+        .collect();
+    fs::write(&ranks_path, ranks).unwrap();
+    let special_path = dir.path().join("special.json");
+    fs::write(&special_path, r#"{"<|end|>": 256}"#).unwrap();
+
+    // One token per byte of "hi", plus one for "<|end|>".
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.arg("-t")
+        .arg("--encoding-file")
+        .arg(&ranks_path)
+        .args(["--pattern", r"\S+"])
+        .arg("--special")
+        .arg(&special_path)
+        .write_stdin("hi<|end|>")
+        .assert()
+        .success()
+        .stdout("3\n");
 }
 
 #[test]
@@ -139,16 +728,10 @@ fn test_error_code_without_termination() {
         .assert()
         .failure()
         .code(1) // Expect an error code of 1
-        .stdout(predicate::str::contains(
-            "       1        5       26        7",
-        )) // Output for first existing file
+        .stdout(predicate::str::contains(" 1  5 26  7")) // Output for first existing file
         .stderr(predicate::str::contains("No such file")) // Error message for non-existent file
-        .stdout(predicate::str::contains(
-            "       1        5       31        7",
-        )) // Output for second existing file
-        .stdout(predicate::str::contains(
-            "       2       10       57       14 total",
-        )); // Total count
+        .stdout(predicate::str::contains(" 1  5 31  7")) // Output for second existing file
+        .stdout(predicate::str::contains(" 2 10 57 14 total")); // Total count
 
     // Verify that the process didn't terminate early by checking if it processed both existing files
     let output = cmd.output().unwrap();
@@ -190,9 +773,7 @@ fn test_permission_denied() {
     cmd.arg(&file_path)
         .assert()
         .success()
-        .stdout(predicate::str::contains(
-            "       1        5       20        6",
-        ));
+        .stdout(predicate::str::contains(" 1  5 20  6"));
     fs::remove_file(&file_path).unwrap();
 }
 
@@ -215,3 +796,196 @@ fn test_directory_counting() {
         )));
     fs::remove_dir_all(dir).unwrap();
 }
+
+/// Displays the path of `components` below `dir`, joined one at a time so
+/// that it uses the separator the walker prints on this platform.
+fn display_path(dir: &Path, components: &[&str]) -> String {
+    let path = components
+        .iter()
+        .fold(dir.to_path_buf(), |path, component| path.join(component));
+    path.display().to_string()
+}
+
+#[test]
+fn test_recursive() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("sub/deep")).unwrap();
+    fs::create_dir(dir.path().join(".hidden")).unwrap();
+    fs::write(dir.path().join("one.txt"), "one\n").unwrap();
+    fs::write(dir.path().join("sub/two.txt"), "two words\n").unwrap();
+    fs::write(dir.path().join("sub/deep/three.txt"), "three more words\n").unwrap();
+    fs::write(dir.path().join(".hidden/secret.txt"), "secret\n").unwrap();
+    fs::write(dir.path().join(".dotfile"), "dot\n").unwrap();
+    let path = |components: &[&str]| display_path(dir.path(), components);
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-r", "-w"])
+        .env("LC_ALL", "en_US.UTF-8")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(format!(
+            "1 {}\n3 {}\n2 {}\n6 total\n",
+            path(&["one.txt"]),
+            path(&["sub", "deep", "three.txt"]),
+            path(&["sub", "two.txt"]),
+        ));
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-r", "-w", "--max-depth", "2"])
+        .env("LC_ALL", "en_US.UTF-8")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(format!(
+            "1 {}\n2 {}\n3 total\n",
+            path(&["one.txt"]),
+            path(&["sub", "two.txt"]),
+        ));
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-r", "-w", "--hidden", "--total", "only"])
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout("8\n");
+}
+
+#[test]
+fn test_recursive_keeps_file_arguments() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join(".hidden.txt");
+    fs::write(&file_path, "one two\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-r", "-w"])
+        .arg(&file_path)
+        .assert()
+        .success()
+        .stdout(format!("2 {}\n", file_path.display()));
+}
+
+#[test]
+fn test_recursive_ignore_files() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join(".git")).unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::create_dir(dir.path().join("target")).unwrap();
+    fs::create_dir(dir.path().join("docs")).unwrap();
+    fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+    fs::write(dir.path().join(".ignore"), "*.log\n").unwrap();
+    fs::write(dir.path().join(".tcignore"), "docs/\n").unwrap();
+    fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(dir.path().join("target/build.rs"), "generated\n").unwrap();
+    fs::write(dir.path().join("docs/guide.md"), "# Guide\n").unwrap();
+    fs::write(dir.path().join("run.log"), "log line\n").unwrap();
+    let path = |components: &[&str]| display_path(dir.path(), components);
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-r", "-l"])
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(format!("1 {}\n", path(&["src", "main.rs"])));
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-r", "-l", "--no-ignore"])
+        .env("LC_ALL", "en_US.UTF-8")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(format!(
+            "1 {}\n1 {}\n1 {}\n1 {}\n4 total\n",
+            path(&["docs", "guide.md"]),
+            path(&["run.log"]),
+            path(&["src", "main.rs"]),
+            path(&["target", "build.rs"]),
+        ));
+
+    // Outside of a git repository only .gitignore stops applying.
+    fs::remove_dir(dir.path().join(".git")).unwrap();
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["-r", "-l"])
+        .env("LC_ALL", "en_US.UTF-8")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(format!(
+            "1 {}\n1 {}\n2 total\n",
+            path(&["src", "main.rs"]),
+            path(&["target", "build.rs"]),
+        ));
+}
+
+#[test]
+fn test_include_and_exclude() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("src")).unwrap();
+    fs::create_dir_all(dir.path().join("tests/unit")).unwrap();
+    fs::write(dir.path().join("src/lib.rs"), "pub fn lib() {}\n").unwrap();
+    fs::write(dir.path().join("tests/cli.rs"), "#[test]\n").unwrap();
+    fs::write(dir.path().join("tests/unit/parse.rs"), "#[test]\n").unwrap();
+    fs::write(dir.path().join("README.md"), "# Readme\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.current_dir(dir.path())
+        .args([
+            "-r",
+            "-w",
+            "--include",
+            "*.rs",
+            "--exclude",
+            "tests/**",
+            ".",
+        ])
+        .assert()
+        .success()
+        .stdout(format!(
+            "4 {}\n",
+            Path::new(".").join("src").join("lib.rs").display()
+        ));
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.current_dir(dir.path())
+        .args(["-w", "--exclude", "*.md", "README.md", "src/lib.rs"])
+        .assert()
+        .success()
+        .stdout("4 src/lib.rs\n");
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.args(["--include", "[abc"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--include <GLOB>"));
+}
+
+#[test]
+fn test_include_and_exclude_below_walked_directory() {
+    let dir = tempdir().unwrap();
+    let root = dir.path().join("project");
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(root.join("tests")).unwrap();
+    fs::create_dir_all(root.join("target").join("debug")).unwrap();
+    fs::write(root.join("src").join("lib.rs"), "pub fn lib() {}\n").unwrap();
+    fs::write(root.join("tests").join("cli.rs"), "#[test]\n").unwrap();
+    fs::write(root.join("target").join("debug").join("out.rs"), "x\n").unwrap();
+
+    let lib = root.join("src").join("lib.rs");
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.arg("-r")
+        .arg("-w")
+        .args(["--include", "src/*.rs", "--exclude", "target"])
+        .arg(&root)
+        .assert()
+        .success()
+        .stdout(format!("4 {}\n", lib.display()));
+
+    let mut cmd = Command::cargo_bin("tc").unwrap();
+    cmd.arg("-r")
+        .arg("-w")
+        .args(["--exclude", "tests/**", "--exclude", "target/**"])
+        .arg(&root)
+        .assert()
+        .success()
+        .stdout(format!("4 {}\n", lib.display()));
+}